@@ -1,15 +1,39 @@
 use std::{
-    fs::{self, File},
-    io::{BufWriter, Write},
+    collections::HashSet,
+    env,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
 };
 
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use directories::ProjectDirs;
-use nix::unistd::Uid;
 use regex::Regex;
 use tui::widgets::ListState;
 
-use crate::{editinglist::EditingList, statefullist::StatefulList};
+use crate::{
+    config::{Config, Verification},
+    diff::{self, DiffLine},
+    dropin,
+    editinglist::EditingList,
+    escalate::{self, Escalator},
+    install::OfflineManager,
+    ledger::{self, EntryState, Ledger, LedgerEntry},
+    lint::Linter,
+    mixin,
+    remove::{Removal, RemovalStep},
+    scope::Scope,
+    statefullist::StatefulList,
+    status::{StatusProvider, SystemctlStatus, UnitStatus},
+    systemctl::{self, PostSaveAction, UnitManager},
+    unitfile::UnitFile,
+    unitpaths,
+    unittype::UnitType,
+    verify::{self, Diagnostic, Severity, Verifier},
+    writer::{self, WriteOutcome},
+};
 
 #[derive(PartialEq, Eq, Hash)]
 pub enum AppState {
@@ -18,50 +42,320 @@ pub enum AppState {
     EnteringEditMode,
     ViewService,
     ModifyingService,
+    ChooseTemplateName,
+    ImportUnit,
+    SelectMixins,
+    ChooseScope,
+    ConfirmOverwrite,
+    SaveOpenedUnit,
+    RenameUnit,
+    PostSave,
+    UnitStatus,
+    ConfirmRemoval,
+    ManagedUnits,
+}
+
+enum DropinOutcome {
+    Written(WriteOutcome),
+    /// The edits match the unit again, so an existing override was deleted.
+    Removed(PathBuf),
+    Unchanged,
+}
+
+/// How often the status screen re-reads the unit state and journal.
+const STATUS_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Journal lines shown on the status screen.
+const JOURNAL_LINES: usize = 20;
+
+/// One-off message shown in the status line until the next redraw.
+pub enum StatusMessage {
+    Info(String),
+    Error(String),
+}
+
+/// Where the app keeps its own files.
+pub struct AppPaths {
+    /// Templates, mixins and config.toml.
+    pub config_dir: PathBuf,
+    /// The ledger of units the app wrote.
+    pub ledger: PathBuf,
+}
+
+impl Default for AppPaths {
+    fn default() -> Self {
+        AppPaths {
+            config_dir: App::templates_dir(),
+            ledger: Ledger::default_path(),
+        }
+    }
+}
+
+impl AppPaths {
+    /// Directory holding user mixins, `config_dir`/mixins.
+    pub fn mixins_dir(&self) -> PathBuf {
+        self.config_dir.join("mixins")
+    }
 }
 
 pub struct App {
     pub lhs_list: StatefulList<String>,
+    /// Problems found while loading templates, one per skipped file.
+    pub diagnostics: Vec<String>,
+    pub import_list: StatefulList<String>,
+    pub mixin_list: StatefulList<String>,
+    pub selected_mixins: HashSet<usize>,
     pub app_state: AppState,
     pub service_name: String,
+    pub unit_type: UnitType,
+    pub scope: Scope,
+    pub scope_list: ListState,
+    /// Diff from the unit already on disk to the one about to be saved.
+    pub overwrite_diff: Vec<DiffLine>,
+    existing_unit: String,
+    /// Set while editing an installed unit whose changes are saved as a drop-in.
+    pub dropin_mode: bool,
+    /// File the unit was opened from with `edit`, which saving can write back to.
+    pub opened_path: Option<PathBuf>,
+    pub post_save_list: ListState,
+    pub unit_manager: Box<dyn UnitManager>,
+    pub status_provider: Box<dyn StatusProvider>,
+    pub unit_status: Option<UnitStatus>,
+    pub journal: Vec<String>,
+    last_status_refresh: Option<Instant>,
+    /// Staged root filesystem given with --root.
+    pub root: Option<PathBuf>,
+    /// Writes files in directories only root can write to; `None` when
+    /// running as root or when nothing to escalate with was found.
+    escalator: Option<Box<dyn Escalator>>,
+    pub removal: Option<Removal>,
+    /// Units written so far; `None` if the ledger could not be read, so that
+    /// it is not overwritten.
+    pub ledger: Option<Ledger>,
+    pub managed_list: ListState,
+    /// Name and text of the template the unit being edited started from.
+    source_template: String,
+    source_text: String,
+    /// Checks the unit before it is saved; `None` without systemd-analyze.
+    verifier: Option<Box<dyn Verifier>>,
+    /// What the verifier said about the unit at the last save attempt.
+    pub verify_diagnostics: Vec<Diagnostic>,
+    /// Why the verifier could not check the unit at the last save attempt.
+    verify_failure: Option<String>,
+    /// Refuse to save units the verifier reports errors for.
+    pub block_on_verify_errors: bool,
+    linter: Linter,
+    /// What the lint rules say about the unit as it is now.
+    pub lint_diagnostics: Vec<Diagnostic>,
+    pub config: Config,
+    previous_service_name: String,
+    pub validation: Vec<String>,
     pub editing_service: EditingList,
     pub altered_line: Option<(String, String)>,
-    pub just_saved: bool,
+    pub template_name: String,
+    pub placeholders: HashSet<usize>,
+    pub status_message: Option<StatusMessage>,
+    paths: AppPaths,
 }
 
 impl App {
-    pub fn new() -> Self {
-        let templates = Self::find_service_templates();
+    /// With a `root`, units are read from and written below that directory
+    /// instead of the running system.
+    pub fn new(root: Option<PathBuf>, config: Config) -> Self {
+        Self::with_paths(root, config, AppPaths::default())
+    }
+
+    /// Like `new`, with the app's own files kept in `paths`.
+    pub fn with_paths(root: Option<PathBuf>, mut config: Config, paths: AppPaths) -> Self {
+        if root.is_some() {
+            // Nothing runs in a staged tree, only its links can change.
+            config
+                .post_save_actions
+                .retain(PostSaveAction::works_offline);
+            if config.post_save_actions.is_empty() {
+                config.post_save_actions = PostSaveAction::ALL
+                    .into_iter()
+                    .filter(PostSaveAction::works_offline)
+                    .collect();
+            }
+        }
+        let (templates, diagnostics) = Self::find_service_templates(&paths.config_dir);
         let mut lhs_list_state = ListState::default();
         lhs_list_state.select(Some(0));
+        let escalate = config.escalate.as_deref();
+        let default_template = config
+            .default_template
+            .as_ref()
+            .and_then(|name| templates.iter().position(|(template, _)| template == name));
 
         let mut app = App {
-            lhs_list: StatefulList::with_items(templates.clone()),
+            lhs_list: StatefulList::with_items(templates),
+            diagnostics,
+            import_list: StatefulList::with_items(vec![]),
+            mixin_list: StatefulList::with_items(mixin::find_mixins(&paths.mixins_dir())),
+            selected_mixins: HashSet::new(),
             app_state: AppState::SelectServiceTemplate,
             service_name: "".to_string(),
+            unit_type: UnitType::Service,
+            scope: config
+                .default_scope
+                .unwrap_or_else(Scope::default_for_current_user),
+            scope_list: ListState::default(),
+            overwrite_diff: vec![],
+            existing_unit: "".to_string(),
+            dropin_mode: false,
+            opened_path: None,
+            post_save_list: ListState::default(),
+            unit_manager: match &root {
+                Some(root) => Box::new(OfflineManager {
+                    root: Some(root.clone()),
+                    escalator: escalate::escalator(escalate, true),
+                }),
+                None => systemctl::default_manager(escalate::escalator(escalate, true)),
+            },
+            status_provider: Box::new(SystemctlStatus),
+            unit_status: None,
+            journal: vec![],
+            last_status_refresh: None,
+            root,
+            escalator: escalate::escalator(escalate, true),
+            removal: None,
+            ledger: None,
+            managed_list: ListState::default(),
+            source_template: "".to_string(),
+            source_text: "".to_string(),
+            verifier: match config.verify {
+                Verification::Off => None,
+                _ => verify::default_verifier(),
+            },
+            verify_diagnostics: vec![],
+            verify_failure: None,
+            block_on_verify_errors: config.verify == Verification::Block,
+            linter: Linter::new(config.lint.clone()),
+            lint_diagnostics: vec![],
+            previous_service_name: "".to_string(),
+            validation: vec![],
             editing_service: EditingList::default(),
             altered_line: None,
-            just_saved: false,
+            template_name: "".to_string(),
+            placeholders: HashSet::new(),
+            status_message: None,
+            config,
+            paths,
         };
-        app.lhs_list.state.select(Some(0));
+        app.lhs_list
+            .state
+            .select(Some(default_template.unwrap_or(0)));
         //app.rhs_list_state.select(Some(0));
+        match Ledger::load(&app.paths.ledger) {
+            Ok(ledger) => app.ledger = Some(ledger),
+            Err(e) => {
+                app.status_message = Some(StatusMessage::Error(format!(
+                    "Unable to read the list of managed units, not recording writes: {e}"
+                )))
+            }
+        }
         app
     }
 
     pub fn handle_keyboard(&mut self, key: KeyEvent) -> bool {
-        if key.modifiers == KeyModifiers::CONTROL {
-            if let KeyCode::Char(c) = key.code {
-                if c == 'v' {
-                    return false;
-                }
-
-                if c == 's' {
-                    if self.app_state == AppState::ViewService {
-                        self.save();
-                        self.just_saved = true;
-                    }
-                };
+        let keys = &self.config.keys;
+        if keys.exit.matches(&key) {
+            return false;
+        }
+        if self.app_state == AppState::ViewService {
+            if keys.save.matches(&key) {
+                self.start_save();
+                return true;
+            }
+            if keys.save_template.matches(&key) {
+                self.app_state = AppState::ChooseTemplateName;
+                return true;
+            }
+            if keys.placeholder.matches(&key) {
+                self.toggle_placeholder();
+                return true;
+            }
+            if keys.edit.matches(&key) {
+                self.edit_externally();
+                return true;
+            }
+        }
+        if self.app_state == AppState::SelectServiceTemplate {
+            if keys.quit.matches(&key) {
+                return false;
+            }
+            if keys.import.matches(&key) {
+                self.open_import();
+                return true;
+            }
+            if keys.managed.matches(&key) {
+                let empty = self.managed_units().is_empty();
+                self.managed_list.select((!empty).then_some(0));
+                self.app_state = AppState::ManagedUnits;
+                return true;
             }
+        }
+        if self.app_state == AppState::ImportUnit {
+            if keys.import_template.matches(&key) {
+                self.import_as_template();
+                return true;
+            }
+            if keys.override_unit.matches(&key) {
+                self.override_installed_unit();
+                return true;
+            }
+            if keys.remove_unit.matches(&key) {
+                self.plan_removal();
+                return true;
+            }
+            if keys.edit_unit.matches(&key) {
+                self.edit_installed_unit();
+                return true;
+            }
+        }
+        if self.app_state == AppState::SelectMixins && keys.toggle_mixin.matches(&key) {
+            self.toggle_mixin();
+            return true;
+        }
+        if self.app_state == AppState::ConfirmOverwrite {
+            if keys.overwrite.matches(&key) {
+                self.write_unit();
+                return true;
+            }
+            if keys.rename.matches(&key) {
+                self.previous_service_name = self.service_name.clone();
+                self.service_name.clear();
+                self.app_state = AppState::RenameUnit;
+                return true;
+            }
+            if keys.dropin.matches(&key) {
+                self.write_dropin();
+                return true;
+            }
+        }
+        if self.app_state == AppState::SaveOpenedUnit {
+            if keys.in_place.matches(&key) {
+                self.dropin_mode = false;
+                self.save_in_place();
+                return true;
+            }
+            if keys.dropin.matches(&key) {
+                self.dropin_mode = true;
+                self.save_checked();
+                return true;
+            }
+            if keys.copy_to_scope.matches(&key) {
+                self.dropin_mode = false;
+                self.choose_scope();
+                return true;
+            }
+        }
+        if self.app_state == AppState::PostSave && keys.status.matches(&key) {
+            self.show_unit_status();
+            return true;
+        }
+        if key.modifiers == KeyModifiers::CONTROL {
             return true;
         }
 
@@ -69,7 +363,8 @@ impl App {
 
         match key.code {
             KeyCode::Enter => match self.app_state {
-                AppState::SelectServiceTemplate => self.app_state = AppState::ChooseServiceName,
+                AppState::SelectServiceTemplate => self.app_state = AppState::SelectMixins,
+                AppState::SelectMixins => self.app_state = AppState::ChooseServiceName,
                 AppState::ChooseServiceName => self.app_state = AppState::EnteringEditMode,
                 AppState::ViewService => {
                     self.store_key_value();
@@ -79,6 +374,28 @@ impl App {
                     self.modify_unit();
                     self.app_state = AppState::ViewService;
                 }
+                AppState::ChooseTemplateName => {
+                    if !self.template_name.is_empty() {
+                        self.save_template();
+                        self.app_state = AppState::ViewService;
+                    }
+                }
+                AppState::ImportUnit => self.start_from_installed_unit(),
+                AppState::ChooseScope => {
+                    self.scope = Scope::ALL[self.scope_list.selected().unwrap_or(0)];
+                    self.save_checked();
+                }
+                AppState::ConfirmOverwrite => (),
+                AppState::SaveOpenedUnit => (),
+                AppState::RenameUnit => {
+                    if !self.service_name.is_empty() {
+                        self.save_checked();
+                    }
+                }
+                AppState::PostSave => self.run_post_save_action(),
+                AppState::UnitStatus => self.refresh_unit_status(),
+                AppState::ConfirmRemoval => self.run_removal(),
+                AppState::ManagedUnits => self.reopen_managed_unit(),
             },
             KeyCode::Left => (),
             KeyCode::Right => (),
@@ -88,6 +405,27 @@ impl App {
                 AppState::ViewService => self.previous_content_item(),
                 AppState::EnteringEditMode => (),
                 AppState::ModifyingService => (),
+                AppState::ChooseTemplateName => (),
+                AppState::ImportUnit => self.import_list.previous(),
+                AppState::SelectMixins => self.mixin_list.previous(),
+                AppState::ChooseScope => select_cyclic(&mut self.scope_list, Scope::ALL.len(), -1),
+                AppState::ConfirmOverwrite => (),
+                AppState::SaveOpenedUnit => (),
+                AppState::RenameUnit => (),
+                AppState::PostSave => select_cyclic(
+                    &mut self.post_save_list,
+                    self.config.post_save_actions.len(),
+                    -1,
+                ),
+                AppState::UnitStatus => (),
+                AppState::ConfirmRemoval => (),
+                AppState::ManagedUnits => {
+                    let len = self
+                        .ledger
+                        .as_ref()
+                        .map_or(0, |ledger| ledger.entries.len());
+                    select_cyclic(&mut self.managed_list, len, -1)
+                }
             },
             KeyCode::Down => match self.app_state {
                 AppState::SelectServiceTemplate => self.lhs_list.next(),
@@ -95,35 +433,87 @@ impl App {
                 AppState::ViewService => self.next_content_item(),
                 AppState::EnteringEditMode => (),
                 AppState::ModifyingService => (),
+                AppState::ChooseTemplateName => (),
+                AppState::ImportUnit => self.import_list.next(),
+                AppState::SelectMixins => self.mixin_list.next(),
+                AppState::ChooseScope => select_cyclic(&mut self.scope_list, Scope::ALL.len(), 1),
+                AppState::ConfirmOverwrite => (),
+                AppState::SaveOpenedUnit => (),
+                AppState::RenameUnit => (),
+                AppState::PostSave => select_cyclic(
+                    &mut self.post_save_list,
+                    self.config.post_save_actions.len(),
+                    1,
+                ),
+                AppState::UnitStatus => (),
+                AppState::ConfirmRemoval => (),
+                AppState::ManagedUnits => {
+                    let len = self
+                        .ledger
+                        .as_ref()
+                        .map_or(0, |ledger| ledger.entries.len());
+                    select_cyclic(&mut self.managed_list, len, 1)
+                }
             },
             KeyCode::PageUp => self.first_content_item(),
             KeyCode::PageDown => self.last_content_item(),
             KeyCode::Delete => (),
             KeyCode::F(_) => (),
             KeyCode::Char(ch) => match self.app_state {
-                AppState::SelectServiceTemplate => {
-                    if ch == 'q' {
-                        return false;
-                    }
-                }
+                AppState::SelectServiceTemplate => (),
                 AppState::ChooseServiceName => self.service_name.push(ch),
                 AppState::ViewService => (),
                 AppState::EnteringEditMode => (),
                 AppState::ModifyingService => {
                     self.modifying_service_push(ch);
                 }
+                AppState::ChooseTemplateName => self.template_name.push(ch),
+                AppState::ImportUnit => (),
+                AppState::SelectMixins => (),
+                AppState::ChooseScope => (),
+                AppState::ConfirmOverwrite => (),
+                AppState::SaveOpenedUnit => (),
+                AppState::RenameUnit => self.service_name.push(ch),
+                AppState::PostSave => (),
+                AppState::UnitStatus => (),
+                AppState::ConfirmRemoval => (),
+                AppState::ManagedUnits => (),
             },
             KeyCode::Backspace => {
-                if let AppState::ChooseServiceName = self.app_state {
+                if let AppState::ChooseServiceName | AppState::RenameUnit = self.app_state {
                     self.service_name.pop();
                 } else if let AppState::ModifyingService = self.app_state {
                     self.modifying_service_pop();
+                } else if let AppState::ChooseTemplateName = self.app_state {
+                    self.template_name.pop();
                 }
             }
             KeyCode::Esc => {
                 if let AppState::ChooseServiceName = self.app_state {
                     self.app_state = AppState::SelectServiceTemplate;
                     self.service_name.clear();
+                } else if let AppState::ChooseTemplateName = self.app_state {
+                    self.app_state = AppState::ViewService;
+                    self.template_name.clear();
+                } else if let AppState::ImportUnit | AppState::ManagedUnits = self.app_state {
+                    self.app_state = AppState::SelectServiceTemplate;
+                } else if let AppState::SelectMixins = self.app_state {
+                    self.app_state = AppState::SelectServiceTemplate;
+                    self.selected_mixins.clear();
+                } else if let AppState::ChooseScope
+                | AppState::ConfirmOverwrite
+                | AppState::SaveOpenedUnit
+                | AppState::PostSave = self.app_state
+                {
+                    self.app_state = AppState::ViewService;
+                } else if let AppState::ConfirmRemoval = self.app_state {
+                    self.removal = None;
+                    self.app_state = AppState::ImportUnit;
+                } else if let AppState::UnitStatus = self.app_state {
+                    self.app_state = AppState::PostSave;
+                } else if let AppState::RenameUnit = self.app_state {
+                    self.service_name = std::mem::take(&mut self.previous_service_name);
+                    self.app_state = AppState::ViewService;
                 }
             }
             KeyCode::Modifier(_) => (),
@@ -133,24 +523,70 @@ impl App {
         returncode
     }
 
-    pub fn find_service_templates() -> Vec<(String, String)> {
+    /// Directory holding the user's templates, ~/.config/create-systemd-service.
+    pub fn templates_dir() -> PathBuf {
         let proj_dirs = ProjectDirs::from("_", "_", "create-systemd-service").unwrap();
-        let templates_path = proj_dirs.config_dir();
+        proj_dirs.config_dir().to_path_buf()
+    }
 
+    /// Templates of every unit type in `templates_path`, keyed by file name,
+    /// plus a message for each file that could not be loaded. Unit types
+    /// without a template in the directory get a built-in skeleton named
+    /// `new.<suffix>`.
+    pub fn find_service_templates(templates_path: &Path) -> (Vec<(String, String)>, Vec<String>) {
         let mut v1: Vec<(String, String)> = vec![];
+        let mut diagnostics = vec![];
 
-        for template_result in fs::read_dir(templates_path).unwrap() {
-            if let Ok(template) = template_result {
-                let file_name = template.file_name().into_string().unwrap();
-                if file_name.contains(".service") {
-                    let template_name = file_name.replace(".service", "");
-                    let template_contents = fs::read_to_string(template.path()).unwrap();
-                    v1.push((template_name, template_contents));
+        match fs::read_dir(templates_path) {
+            Ok(entries) => {
+                for template in entries {
+                    match Self::load_template(template) {
+                        Ok(Some(template)) => v1.push(template),
+                        Ok(None) => (),
+                        Err(e) => diagnostics.push(e),
+                    }
                 }
             }
+            Err(e) => diagnostics.push(format!("{}: {e}", templates_path.display())),
         }
         v1.sort_by_key(|f| f.0.clone());
-        v1
+        diagnostics.sort();
+
+        for unit_type in UnitType::ALL {
+            let has_template = v1
+                .iter()
+                .any(|(name, _)| UnitType::from_file_name(name) == Some(unit_type));
+            if !has_template {
+                v1.push((unit_type.file_name("new"), unit_type.default_template()));
+            }
+        }
+        (v1, diagnostics)
+    }
+
+    /// Reads one directory entry. Files that are not unit templates are skipped
+    /// with `Ok(None)`; unreadable or unparsable templates become an error message.
+    fn load_template(
+        entry: std::io::Result<fs::DirEntry>,
+    ) -> Result<Option<(String, String)>, String> {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let file_name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| format!("{}: file name is not valid UTF-8", name.to_string_lossy()))?;
+
+        let is_template = match UnitType::from_file_name(&file_name) {
+            Some(unit_type) => file_name.len() > unit_type.suffix().len() + 1,
+            None => false,
+        };
+        if !is_template || !path.is_file() {
+            return Ok(None);
+        }
+
+        let contents = fs::read_to_string(&path).map_err(|e| format!("{file_name}: {e}"))?;
+        UnitFile::parse(&contents).map_err(|e| format!("{file_name}: {e}"))?;
+
+        Ok(Some((file_name, contents)))
     }
 
     pub fn initialise_edit(&mut self) {
@@ -166,12 +602,61 @@ impl App {
             .expect("Highlighted value should always be valid.")
             .1
             .to_string();
-        self.editing_service.editing_text = editing_text.lines().map(|s| s.to_owned()).collect();
+        self.source_template = self.lhs_list.items[index].0.clone();
+        self.source_text = editing_text.clone();
+        let editing_text = self.apply_selected_mixins(editing_text);
+        let unit_type =
+            UnitType::from_file_name(&self.lhs_list.items[index].0).unwrap_or(UnitType::Service);
+        self.dropin_mode = false;
+        self.opened_path = None;
+        self.start_editing(&editing_text, unit_type);
+    }
+
+    fn start_editing(&mut self, text: &str, unit_type: UnitType) {
+        self.editing_service.editing_text = text.lines().map(|s| s.to_owned()).collect();
+        self.editing_service.state.select(Some(0));
         self.editing_service.next();
+        self.placeholders.clear();
+        self.verify_diagnostics.clear();
+        self.unit_type = unit_type;
+        self.validate();
 
         self.app_state = AppState::ViewService;
     }
 
+    fn toggle_mixin(&mut self) {
+        if let Some(index) = self.mixin_list.state.selected() {
+            if !self.selected_mixins.remove(&index) {
+                self.selected_mixins.insert(index);
+            }
+        }
+    }
+
+    /// Layers the ticked mixins, in list order, onto the template text.
+    fn apply_selected_mixins(&mut self, template: String) -> String {
+        let mixins: Vec<&str> = self
+            .mixin_list
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.selected_mixins.contains(index))
+            .map(|(_, (_, contents))| contents.as_str())
+            .collect();
+
+        if mixins.is_empty() {
+            return template;
+        }
+
+        match mixin::apply_mixins(&template, &mixins) {
+            Ok(merged) => merged,
+            Err(e) => {
+                self.status_message =
+                    Some(StatusMessage::Error(format!("Unable to apply mixins: {e}")));
+                template
+            }
+        }
+    }
+
     fn next_content_item(&mut self) {
         self.editing_service.next();
     }
@@ -220,25 +705,1153 @@ impl App {
         let re = Regex::new(r"^(?P<key>[^=;#]+)=(?P<value>[^;#]*)").unwrap();
         let new_line = re.replace_all(item, format!("$key={new_text}")).to_string();
         self.editing_service.editing_text[index] = new_line;
+        self.validate();
     }
 
-    fn save(&mut self) {
-        let is_root = Uid::effective().is_root();
-        let file_name: String;
-        let service_name = self.service_name.to_string();
-        if is_root {
-            file_name = format!("/etc/systemd/system/{service_name}.service");
-        } else {
-            file_name = format!("~/.config/systemd/user/{service_name}.service")
+    /// Re-checks the unit being edited against the rules for its type and
+    /// the lint rules.
+    fn validate(&mut self) {
+        let contents = self.selected_template_contents();
+        self.validation = match UnitFile::parse(&contents) {
+            Ok(unit) => self.unit_type.validate(&unit),
+            Err(e) => vec![e.to_string()],
+        };
+        self.lint_diagnostics = self.linter.lint(&contents, self.unit_type);
+    }
+
+    /// File name of the unit being created, e.g. "foo.timer".
+    pub fn unit_file_name(&self) -> String {
+        self.unit_type.file_name(&self.service_name)
+    }
+
+    /// Directory units of `scope` are written to, inside the root if one was given.
+    /// The per-user directory only exists on the running system.
+    pub fn unit_dir(&self, scope: Scope) -> Option<PathBuf> {
+        if self.root.is_some() && scope == Scope::User {
+            return None;
         }
+        Some(unitpaths::rebase(self.root.as_deref(), &scope.unit_dir()?))
+    }
 
-        let f = File::create(file_name).expect("Unable to create file");
-        let mut f = BufWriter::new(f);
-        let result = self
-            .editing_service
+    /// Path the unit is written to in the chosen scope.
+    pub fn unit_path(&self) -> io::Result<PathBuf> {
+        let dir = self.unit_dir(self.scope).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                match self.root {
+                    Some(_) => "the user scope is not available with --root",
+                    None => "neither $XDG_CONFIG_HOME nor $HOME is set",
+                },
+            )
+        })?;
+        Ok(dir.join(self.unit_file_name()))
+    }
+
+    /// The unit as it will be written to disk.
+    pub fn rendered_unit(&self) -> String {
+        self.editing_service
             .editing_text
             .iter()
-            .fold(String::new(), |acc, l| acc + l + "\n");
-        f.write_all(result.as_bytes()).expect("all good");
+            .fold(String::new(), |acc, l| acc + l + "\n")
+    }
+
+    /// Asks where to save: a unit opened from a file can go back to it, others
+    /// go to the directory of a scope.
+    fn start_save(&mut self) {
+        match &self.opened_path {
+            Some(_) => {
+                self.overwrite_diff =
+                    diff::unified_diff(&self.existing_unit, &self.rendered_unit());
+                self.app_state = AppState::SaveOpenedUnit;
+            }
+            None => self.choose_scope(),
+        }
+    }
+
+    fn choose_scope(&mut self) {
+        self.scope_list
+            .select(Scope::ALL.iter().position(|s| *s == self.scope));
+        self.app_state = AppState::ChooseScope;
+    }
+
+    /// Verifies the unit, then saves it as a whole or as a drop-in.
+    fn save_checked(&mut self) {
+        if !self.verify_before_save() {
+            return;
+        }
+        if self.dropin_mode {
+            self.write_dropin();
+        } else {
+            self.save_or_confirm();
+        }
+    }
+
+    /// Runs the verifier on the rendered unit. Returns false when it found
+    /// errors, or could not run, and saving such units is blocked.
+    fn verify_before_save(&mut self) -> bool {
+        let (unit, rendered) = (self.unit_file_name(), self.rendered_unit());
+        self.verify_failure = None;
+        let Some(verifier) = &mut self.verifier else {
+            return true;
+        };
+        self.verify_diagnostics = match verifier.verify(self.scope, &unit, &rendered) {
+            Ok(diagnostics) => diagnostics,
+            Err(e) if self.block_on_verify_errors => {
+                self.verify_diagnostics.clear();
+                self.status_message = Some(StatusMessage::Error(format!(
+                    "Not saved, systemd-analyze verify did not run: {e}"
+                )));
+                self.app_state = AppState::ViewService;
+                return false;
+            }
+            Err(e) => {
+                // Saved all the same, `finish_save` says it went unchecked.
+                self.verify_failure = Some(e.to_string());
+                vec![]
+            }
+        };
+
+        let mut errors = self
+            .verify_diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error);
+        match errors.next() {
+            Some(first) if self.block_on_verify_errors => {
+                let more = errors.count();
+                let mut message = format!(
+                    "Not saved, systemd-analyze verify failed: {}",
+                    first.message
+                );
+                if more > 0 {
+                    message += &format!(" (+{more} more)");
+                }
+                self.status_message = Some(StatusMessage::Error(message));
+                self.app_state = AppState::ViewService;
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Verifier findings followed by lint findings.
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.verify_diagnostics
+            .iter()
+            .chain(self.lint_diagnostics.iter())
+    }
+
+    /// Verifier or lint findings for the highlighted line of the editor.
+    pub fn selected_line_diagnostic(&self) -> Option<&Diagnostic> {
+        let line = self.editing_service.state.selected()? + 1;
+        self.diagnostics().find(|d| d.line == Some(line))
+    }
+
+    /// Saves the unit, unless a different unit of the same name already exists,
+    /// in which case the diff is shown and the user decides what to do.
+    fn save_or_confirm(&mut self) {
+        let existing = self
+            .unit_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok());
+
+        match existing {
+            Some(existing) if existing != self.rendered_unit() => {
+                self.overwrite_diff = diff::unified_diff(&existing, &self.rendered_unit());
+                self.existing_unit = existing;
+                self.app_state = AppState::ConfirmOverwrite;
+            }
+            _ => self.write_unit(),
+        }
+    }
+
+    fn write_unit(&mut self) {
+        let path = self.unit_path();
+        self.write_unit_at(path);
+    }
+
+    /// Writes the unit opened with `edit` back to the file it came from.
+    fn save_in_place(&mut self) {
+        let Some(path) = self.opened_path.clone() else {
+            return;
+        };
+        if self.verify_before_save() {
+            self.write_unit_at(Ok(path));
+        }
+    }
+
+    fn write_unit_at(&mut self, path: io::Result<PathBuf>) {
+        let contents = self.rendered_unit();
+        let message = match path.and_then(|path| self.write_file(&path, &contents)) {
+            Ok(outcome) => {
+                let mut message = match &outcome.backup {
+                    Some(backup) => format!(
+                        "Saved in {}, previous version kept as {}.",
+                        outcome.path.display(),
+                        backup.display()
+                    ),
+                    None => format!("Saved in {}.", outcome.path.display()),
+                };
+                if let Err(e) = self.record_write(&outcome) {
+                    message += &format!(" Unable to record it as managed: {e}.");
+                }
+                StatusMessage::Info(message)
+            }
+            Err(e) => StatusMessage::Error(format!("Unable to save: {e}")),
+        };
+        self.finish_save(message);
+    }
+
+    /// Adds the unit just written to `path` to the ledger.
+    fn record_write(&mut self, outcome: &WriteOutcome) -> io::Result<()> {
+        let rendered = self.rendered_unit();
+        let entry = LedgerEntry {
+            unit: self.unit_file_name(),
+            template: self.source_template.clone(),
+            variables: ledger::template_variables(&self.source_text, &rendered),
+            scope: self.scope,
+            path: outcome.path.clone(),
+            written_at: writer::timestamp(),
+            original_backup: outcome.backup.clone(),
+            sha256: ledger::content_hash(&rendered),
+        };
+        match &mut self.ledger {
+            Some(ledger) => ledger.record(entry),
+            None => Ok(()),
+        }
+    }
+
+    /// Recorded units with how each compares to the file on disk.
+    pub fn managed_units(&self) -> Vec<(&LedgerEntry, EntryState)> {
+        self.ledger
+            .iter()
+            .flat_map(|ledger| &ledger.entries)
+            .map(|entry| (entry, entry.state()))
+            .collect()
+    }
+
+    /// Opens the highlighted managed unit in the editor. A unit deleted
+    /// outside the tool is rendered again from its template and variables.
+    fn reopen_managed_unit(&mut self) {
+        let Some(entry) = self
+            .managed_list
+            .selected()
+            .and_then(|index| self.managed_units().get(index).map(|(e, _)| (*e).clone()))
+        else {
+            return;
+        };
+        let Some(unit_type) = UnitType::from_file_name(&entry.unit) else {
+            return;
+        };
+
+        let template = self
+            .lhs_list
+            .items
+            .iter()
+            .find(|(name, _)| *name == entry.template)
+            .map(|(_, text)| text.clone());
+        let text = match (fs::read_to_string(&entry.path), &template) {
+            (Ok(contents), _) => contents,
+            (Err(_), Some(template)) => ledger::fill_variables(template, &entry.variables),
+            (Err(_), None) => {
+                self.status_message = Some(StatusMessage::Error(format!(
+                    "{} was deleted and its template {} is gone, unable to recreate it.",
+                    entry.unit, entry.template
+                )));
+                return;
+            }
+        };
+
+        self.service_name = entry.unit;
+        self.scope = entry.scope;
+        self.source_template = entry.template;
+        self.source_text = template.unwrap_or_default();
+        self.dropin_mode = false;
+        self.opened_path = None;
+        self.start_editing(&text, unit_type);
+    }
+
+    /// Opens the unit in the configured editor, falling back to $VISUAL and
+    /// $EDITOR, and takes over what was saved there.
+    fn edit_externally(&mut self) {
+        let editor = self
+            .config
+            .editor
+            .clone()
+            .or_else(|| env::var("VISUAL").ok())
+            .or_else(|| env::var("EDITOR").ok())
+            .unwrap_or_else(|| "vi".to_string());
+        let dir = match writer::private_temp_dir("create-systemd-service") {
+            Ok(dir) => dir,
+            Err(e) => {
+                self.status_message = Some(StatusMessage::Error(format!(
+                    "Unable to create a temporary directory to edit the unit in: {e}"
+                )));
+                return;
+            }
+        };
+        let path = dir.join(self.unit_file_name());
+
+        let edited = fs::write(&path, self.rendered_unit()).and_then(|()| {
+            let status = escalate::with_terminal_suspended(|| {
+                process::Command::new("sh")
+                    .arg("-c")
+                    .arg(format!("{editor} \"$1\""))
+                    .arg("sh")
+                    .arg(&path)
+                    .status()
+            })?;
+            if !status.success() {
+                return Err(io::Error::other(format!("{editor} exited with {status}")));
+            }
+            fs::read_to_string(&path)
+        });
+        let _ = fs::remove_dir_all(&dir);
+
+        match edited.map(|text| (UnitFile::parse(&text), text)) {
+            Ok((Ok(_), text)) => {
+                let unit_type = self.unit_type;
+                self.start_editing(&text, unit_type);
+            }
+            Ok((Err(e), _)) => {
+                self.status_message = Some(StatusMessage::Error(format!(
+                    "Kept the unit as it was, the edited version does not parse: {e}"
+                )))
+            }
+            Err(e) => {
+                self.status_message = Some(StatusMessage::Error(format!(
+                    "Unable to edit the unit with {editor}: {e}"
+                )))
+            }
+        }
+    }
+
+    /// Reports how saving went and, if it worked, offers the post-save actions.
+    fn finish_save(&mut self, mut message: StatusMessage) {
+        if let (StatusMessage::Info(text), Some(e)) = (&mut message, self.verify_failure.take()) {
+            *text += &format!(" Not verified, systemd-analyze verify did not run: {e}.");
+        }
+        self.app_state = match message {
+            StatusMessage::Info(_) => {
+                self.post_save_list.select(Some(0));
+                AppState::PostSave
+            }
+            StatusMessage::Error(_) => AppState::ViewService,
+        };
+        self.status_message = Some(message);
+    }
+
+    fn run_post_save_action(&mut self) {
+        let action = self.config.post_save_actions[self.post_save_list.selected().unwrap_or(0)];
+        let unit = self.unit_file_name();
+        if let Err(e) = action.run(self.unit_manager.as_mut(), self.scope, &unit) {
+            self.status_message = Some(StatusMessage::Error(e.to_string()));
+            return;
+        }
+
+        let mut message = format!("{} {unit}: done", action.label());
+        if action.affects_state() {
+            if let Ok(state) = self.unit_manager.unit_state(self.scope, &unit) {
+                message += &format!(", now {} ({})", state.active_state, state.sub_state);
+            }
+            self.show_unit_status();
+        }
+        self.status_message = Some(StatusMessage::Info(message + "."));
+    }
+
+    fn show_unit_status(&mut self) {
+        self.app_state = AppState::UnitStatus;
+        self.refresh_unit_status();
+    }
+
+    /// Called between key presses; keeps the status screen up to date.
+    pub fn on_tick(&mut self) {
+        let due = match self.last_status_refresh {
+            Some(last) => last.elapsed() >= STATUS_REFRESH_INTERVAL,
+            None => true,
+        };
+        if self.app_state == AppState::UnitStatus && due {
+            self.refresh_unit_status();
+        }
+    }
+
+    fn refresh_unit_status(&mut self) {
+        let unit = self.unit_file_name();
+        self.last_status_refresh = Some(Instant::now());
+
+        match self.status_provider.status(self.scope, &unit) {
+            Ok(status) => self.unit_status = Some(status),
+            Err(e) => {
+                self.unit_status = None;
+                self.status_message = Some(StatusMessage::Error(format!(
+                    "Unable to read status of {unit}: {e}"
+                )));
+            }
+        }
+        self.journal = self
+            .status_provider
+            .journal(self.scope, &unit, JOURNAL_LINES)
+            .unwrap_or_else(|e| vec![format!("Unable to read journal: {e}")]);
+    }
+
+    /// Writes a unit or drop-in, through the escalation command if only root
+    /// can write there.
+    fn write_file(&mut self, path: &Path, contents: &str) -> io::Result<WriteOutcome> {
+        writer::write_unit_with(self.escalator.as_deref_mut(), path, contents)
+    }
+
+    fn remove_file(&mut self, path: &Path) -> io::Result<()> {
+        let path_arg = path.to_string_lossy();
+        self.change_path(path, &["rm", "-f", "--", &path_arg], || {
+            fs::remove_file(path)
+        })
+    }
+
+    /// Changes `path` with `direct`, or by running `command` through the
+    /// escalation command if only root can change it.
+    fn change_path(
+        &mut self,
+        path: &Path,
+        command: &[&str],
+        direct: impl FnOnce() -> io::Result<()>,
+    ) -> io::Result<()> {
+        match &mut self.escalator {
+            Some(escalator) if escalator.needed_for(path) => {
+                escalator.run(command[0], &command[1..], None)?;
+                Ok(())
+            }
+            _ => direct(),
+        }
+    }
+
+    /// Writes only the directives that differ from the unit on disk to
+    /// `<unit>.d/override.conf`.
+    fn write_dropin(&mut self) {
+        let message = match self.save_dropin() {
+            Ok(DropinOutcome::Written(outcome)) => {
+                StatusMessage::Info(format!("Saved drop-in {}.", outcome.path.display()))
+            }
+            Ok(DropinOutcome::Removed(path)) => StatusMessage::Info(format!(
+                "Nothing differs, removed drop-in {}.",
+                path.display()
+            )),
+            Ok(DropinOutcome::Unchanged) => {
+                StatusMessage::Info("Nothing differs, no drop-in written.".to_string())
+            }
+            Err(e) => StatusMessage::Error(format!("Unable to save drop-in: {e}")),
+        };
+        self.finish_save(message);
+    }
+
+    /// Path of the drop-in for the unit being edited in the chosen scope.
+    fn dropin_path(&self) -> io::Result<PathBuf> {
+        let unit_path = self.unit_path()?;
+        Ok(PathBuf::from(format!("{}.d", unit_path.display())).join(dropin::OVERRIDE_FILE_NAME))
+    }
+
+    fn save_dropin(&mut self) -> Result<DropinOutcome, Box<dyn Error>> {
+        let original = UnitFile::parse(&self.existing_unit)?;
+        let edited = UnitFile::parse(&self.rendered_unit())?;
+        let dropin = dropin::compute_dropin(&original, &edited);
+        let path = self.dropin_path()?;
+
+        if dropin.sections.is_empty() {
+            // A stale override would keep applying the changes the user just undid.
+            if path.exists() {
+                self.remove_file(&path)?;
+                return Ok(DropinOutcome::Removed(path));
+            }
+            return Ok(DropinOutcome::Unchanged);
+        }
+
+        Ok(DropinOutcome::Written(
+            self.write_file(&path, &dropin.to_string())?,
+        ))
+    }
+
+    fn toggle_placeholder(&mut self) {
+        let index = self.editing_service.state.selected().unwrap();
+        if !self.placeholders.remove(&index) {
+            self.placeholders.insert(index);
+        }
+    }
+
+    /// Contents of the unit with every marked value replaced by a `{{Key}}` placeholder.
+    pub fn template_contents(&self) -> String {
+        let re = Regex::new(r"^(?P<key>[^=;#]+)=(?P<value>[^;#]*)").unwrap();
+        self.editing_service.editing_text.iter().enumerate().fold(
+            String::new(),
+            |acc, (index, line)| {
+                let line = if self.placeholders.contains(&index) {
+                    re.replace(line, "$key={{$key}}").to_string()
+                } else {
+                    line.to_string()
+                };
+                acc + &line + "\n"
+            },
+        )
+    }
+
+    fn save_template(&mut self) {
+        if !is_valid_template_name(&self.template_name) {
+            self.status_message = Some(StatusMessage::Error(format!(
+                "Invalid template name {:?}, it may not contain \"/\" or start with \".\".",
+                self.template_name
+            )));
+            self.template_name.clear();
+            return;
+        }
+        let template_name = self.unit_type.file_name(&self.template_name);
+        let file_name = self.paths.config_dir.join(&template_name);
+
+        match fs::write(&file_name, self.template_contents()) {
+            Ok(()) => {
+                self.status_message = Some(StatusMessage::Info(format!(
+                    "Saved template {}.",
+                    file_name.display()
+                )));
+                self.reload_templates(&template_name);
+            }
+            Err(e) => {
+                self.status_message = Some(StatusMessage::Error(format!(
+                    "Unable to save template: {e}"
+                )));
+            }
+        }
+        self.template_name.clear();
+    }
+
+    /// Re-reads the template directory and keeps `selected` highlighted if present.
+    fn reload_templates(&mut self, selected: &str) {
+        (self.lhs_list.items, self.diagnostics) =
+            Self::find_service_templates(&self.paths.config_dir);
+        let index = self
+            .lhs_list
+            .items
+            .iter()
+            .position(|(name, _)| name == selected)
+            .unwrap_or(0);
+        self.lhs_list.state.select(Some(index));
+    }
+
+    fn open_import(&mut self) {
+        self.import_list =
+            StatefulList::with_items(unitpaths::find_installed_units(self.root.as_deref()));
+        self.app_state = AppState::ImportUnit;
+    }
+
+    /// Unit name, path and contents of the unit highlighted on the import screen.
+    pub fn selected_installed_unit(&self) -> Option<(&str, &str, String)> {
+        let (name, path) = self
+            .import_list
+            .items
+            .get(self.import_list.state.selected()?)?;
+        let contents = fs::read_to_string(path).ok()?;
+        Some((name, path, contents))
+    }
+
+    /// Works out how to remove the unit highlighted on the import screen and
+    /// shows the plan for confirmation. Only units in the directory a scope
+    /// writes to qualify, vendor units are left alone.
+    fn plan_removal(&mut self) {
+        let Some((name, path, contents)) = self.selected_installed_unit() else {
+            return;
+        };
+        let (name, path) = (name.to_string(), PathBuf::from(path));
+        let Some(scope) = Scope::ALL
+            .into_iter()
+            .find(|scope| self.unit_dir(*scope).as_deref() == path.parent())
+        else {
+            self.status_message = Some(StatusMessage::Error(format!(
+                "{} is not in a directory units are saved to, not removing it.",
+                path.display()
+            )));
+            return;
+        };
+
+        let mut steps = vec![];
+        let running = self
+            .unit_manager
+            .unit_state(scope, &name)
+            .is_ok_and(|state| !matches!(state.active_state.as_str(), "inactive" | ""));
+        if running {
+            steps.push(RemovalStep::Stop);
+        }
+        let installable = UnitFile::parse(&contents)
+            .is_ok_and(|unit| unit.sections.iter().any(|s| s.name == "Install"));
+        if installable {
+            steps.push(RemovalStep::Disable);
+        }
+        let dropins = PathBuf::from(format!("{}.d", path.display()));
+        if dropins.is_dir() {
+            steps.push(RemovalStep::RemoveDropins(dropins));
+        }
+        steps.push(RemovalStep::RemoveUnit(path.clone()));
+        // Only the backup of what the tool's first write replaced is worth
+        // putting back, the ones after it hold versions the tool wrote itself.
+        // Without a ledger entry there is no telling, so backups stay as they are.
+        let entry = self
+            .ledger
+            .iter()
+            .flat_map(|ledger| &ledger.entries)
+            .find(|entry| entry.path == path);
+        if let Some(entry) = entry {
+            let original = entry.original_backup.clone().filter(|b| b.exists());
+            let own: Vec<_> = writer::backups(&path)
+                .into_iter()
+                .map(|(_, backup)| backup)
+                .skip_while(|backup| original.as_ref().is_some_and(|o| o != backup))
+                .filter(|backup| Some(backup) != original.as_ref())
+                .collect();
+            if !own.is_empty() {
+                steps.push(RemovalStep::RemoveBackups(own));
+            }
+            if let Some(backup) = original {
+                steps.push(RemovalStep::RestoreBackup { backup, path });
+            }
+        }
+        // The global user scope has no manager of its own to reload.
+        if scope != Scope::GlobalUser {
+            steps.push(RemovalStep::DaemonReload);
+        }
+
+        self.removal = Some(Removal {
+            unit: name,
+            scope,
+            steps,
+        });
+        self.app_state = AppState::ConfirmRemoval;
+    }
+
+    /// Runs the confirmed removal plan, stopping at the first step that fails.
+    fn run_removal(&mut self) {
+        let Some(removal) = self.removal.take() else {
+            return;
+        };
+
+        let mut message = StatusMessage::Info(format!("Removed {}.", removal.unit));
+        for step in &removal.steps {
+            if let Err(e) = self.run_removal_step(removal.scope, &removal.unit, step) {
+                message = StatusMessage::Error(format!(
+                    "{} failed, stopped removing: {e}",
+                    step.describe(&removal.unit)
+                ));
+                break;
+            }
+            if let (RemovalStep::RemoveUnit(path), Some(ledger)) = (step, &mut self.ledger) {
+                if let Err(e) = ledger.forget(path) {
+                    message = StatusMessage::Error(format!(
+                        "Removed {}, but unable to update the list of managed units: {e}",
+                        removal.unit
+                    ));
+                }
+            }
+        }
+
+        self.open_import();
+        self.status_message = Some(message);
+    }
+
+    fn run_removal_step(&mut self, scope: Scope, unit: &str, step: &RemovalStep) -> io::Result<()> {
+        match step {
+            RemovalStep::Stop => self.unit_manager.stop(scope, unit),
+            RemovalStep::Disable => self.unit_manager.disable(scope, unit),
+            RemovalStep::RemoveDropins(dir) => {
+                let dir_arg = dir.to_string_lossy();
+                self.change_path(dir, &["rm", "-rf", "--", &dir_arg], || {
+                    fs::remove_dir_all(dir)
+                })
+            }
+            RemovalStep::RemoveUnit(path) => self.remove_file(path),
+            RemovalStep::RestoreBackup { backup, path } => {
+                let (backup_arg, path_arg) = (backup.to_string_lossy(), path.to_string_lossy());
+                self.change_path(path, &["mv", "-f", "--", &backup_arg, &path_arg], || {
+                    fs::rename(backup, path)
+                })
+            }
+            RemovalStep::RemoveBackups(backups) => backups
+                .iter()
+                .try_for_each(|backup| self.remove_file(backup)),
+            RemovalStep::DaemonReload => self.unit_manager.daemon_reload(scope),
+        }
+    }
+
+    /// Copies the highlighted installed unit into the template directory.
+    fn import_as_template(&mut self) {
+        let Some((name, _, contents)) = self.selected_installed_unit() else {
+            return;
+        };
+        let template_name = name.to_string();
+        let file_name = self.paths.config_dir.join(name);
+
+        match fs::write(&file_name, contents) {
+            Ok(()) => {
+                self.status_message = Some(StatusMessage::Info(format!(
+                    "Imported template {}.",
+                    file_name.display()
+                )));
+                self.reload_templates(&template_name);
+                self.app_state = AppState::SelectServiceTemplate;
+            }
+            Err(e) => {
+                self.status_message = Some(StatusMessage::Error(format!(
+                    "Unable to import template: {e}"
+                )));
+            }
+        }
+    }
+
+    /// Opens the unit file at `path` in the editor, in the scope whose search
+    /// path it is in. Saving can write it back in place, keeping its comments
+    /// and layout, or put the changes in a drop-in.
+    pub fn open_unit_file(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let unit_type = UnitType::from_file_name(&name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{name} is not a known unit type"),
+            )
+        })?;
+
+        if let Some(scope) = unitpaths::scope_of(self.root.as_deref(), path) {
+            self.scope = scope;
+        }
+        self.service_name = name;
+        self.source_template = path.display().to_string();
+        self.source_text.clear();
+        self.existing_unit = contents.clone();
+        self.dropin_mode = false;
+        self.start_editing(&contents, unit_type);
+        self.opened_path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Opens the unit highlighted on the import screen for editing in place.
+    fn edit_installed_unit(&mut self) {
+        let Some((_, path, _)) = self.selected_installed_unit() else {
+            return;
+        };
+        let path = PathBuf::from(path);
+        if let Err(e) = self.open_unit_file(&path) {
+            self.status_message = Some(StatusMessage::Error(format!(
+                "Unable to open {}: {e}",
+                path.display()
+            )));
+        }
+    }
+
+    /// Uses the highlighted installed unit as the starting point for a new service,
+    /// without adding it to the template directory.
+    fn start_from_installed_unit(&mut self) {
+        let Some((name, _, contents)) = self.selected_installed_unit() else {
+            return;
+        };
+        let template_name = format!("installed: {name}");
+
+        self.lhs_list.items.push((template_name, contents));
+        self.lhs_list
+            .state
+            .select(Some(self.lhs_list.items.len() - 1));
+        self.app_state = AppState::ChooseServiceName;
+    }
+
+    /// Opens the highlighted installed unit for editing. Saving writes only the
+    /// changed directives to a drop-in, leaving the unit itself untouched.
+    fn override_installed_unit(&mut self) {
+        let Some((name, _, contents)) = self.selected_installed_unit() else {
+            return;
+        };
+        let name = name.to_string();
+        let Some(unit_type) = UnitType::from_file_name(&name) else {
+            return;
+        };
+
+        self.service_name = name.clone();
+        self.existing_unit = contents;
+        self.dropin_mode = true;
+        self.opened_path = None;
+
+        // Start from the unit as it currently behaves, including an earlier override.
+        let mut editing_text = self.existing_unit.clone();
+        if let Ok(previous) = self.dropin_path().and_then(fs::read_to_string) {
+            match mixin::apply_mixins(&editing_text, &[&previous]) {
+                Ok(merged) => editing_text = merged,
+                Err(e) => {
+                    self.status_message = Some(StatusMessage::Error(format!(
+                        "Ignoring unreadable drop-in for {name}: {e}"
+                    )))
+                }
+            }
+        }
+
+        self.start_editing(&editing_text, unit_type);
+    }
+}
+
+/// Moves the selection of a fixed-size list by `step`, wrapping around at either end.
+fn select_cyclic(state: &mut ListState, len: usize, step: isize) {
+    if len == 0 {
+        return;
+    }
+    let current = state.selected().unwrap_or(0) as isize;
+    state.select(Some((current + step).rem_euclid(len as isize) as usize));
+}
+
+/// Template names become a file in the template directory and nowhere else.
+fn is_valid_template_name(name: &str) -> bool {
+    !name.contains('/') && !name.starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systemctl::RecordingManager;
+
+    /// `StatusProvider` with nothing to report.
+    struct NoStatus;
+
+    impl StatusProvider for NoStatus {
+        fn status(&mut self, _scope: Scope, _unit: &str) -> io::Result<UnitStatus> {
+            Ok(UnitStatus::default())
+        }
+
+        fn journal(
+            &mut self,
+            _scope: Scope,
+            _unit: &str,
+            _lines: usize,
+        ) -> io::Result<Vec<String>> {
+            Ok(vec![])
+        }
+    }
+
+    /// App whose templates and ledger live in a fresh directory named after
+    /// the test, so tests never touch the user's; with `rooted`, units go
+    /// below `root` in that directory too.
+    fn test_app(test: &str, rooted: bool, config: Config) -> (App, PathBuf) {
+        let dir = env::temp_dir().join(format!("css-app-{}-{test}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let paths = AppPaths {
+            config_dir: dir.join("config"),
+            ledger: dir.join("units.json"),
+        };
+        fs::create_dir_all(&paths.config_dir).unwrap();
+        let root = rooted.then(|| dir.join("root"));
+        (App::with_paths(root, config, paths), dir)
+    }
+
+    fn app_with(manager: &RecordingManager, scope: Scope, name: &str) -> App {
+        let (mut app, _) = test_app(name, false, Config::default());
+        app.unit_manager = Box::new(manager.clone());
+        app.status_provider = Box::new(NoStatus);
+        app.scope = scope;
+        app.service_name = name.to_string();
+        app.unit_type = UnitType::Timer;
+        app
+    }
+
+    fn select_action(app: &mut App, action: PostSaveAction) {
+        let index = app
+            .config
+            .post_save_actions
+            .iter()
+            .position(|a| *a == action);
+        app.post_save_list.select(index);
+    }
+
+    #[test]
+    fn template_names_stay_in_the_template_directory() {
+        for name in ["../escape", "a/b", ".hidden"] {
+            assert!(!is_valid_template_name(name), "{name}");
+        }
+        assert!(is_valid_template_name("web.service"));
+    }
+
+    #[test]
+    fn post_save_action_runs_in_the_chosen_scope() {
+        let manager = RecordingManager::default();
+        let mut app = app_with(&manager, Scope::User, "backup");
+        select_action(&mut app, PostSaveAction::Enable);
+
+        app.run_post_save_action();
+
+        assert_eq!(
+            *manager.calls.borrow(),
+            [(Scope::User, "enable backup.timer".to_string())]
+        );
+        assert!(matches!(
+            app.status_message,
+            Some(StatusMessage::Info(ref m)) if m == "enable backup.timer: done."
+        ));
+    }
+
+    #[test]
+    fn post_save_action_that_starts_reports_the_state() {
+        let manager = RecordingManager::default();
+        let mut app = app_with(&manager, Scope::System, "backup");
+        select_action(&mut app, PostSaveAction::EnableNow);
+
+        app.run_post_save_action();
+
+        assert_eq!(
+            *manager.calls.borrow(),
+            [
+                (Scope::System, "enable --now backup.timer".to_string()),
+                (Scope::System, "unit-state backup.timer".to_string()),
+            ]
+        );
+        assert!(matches!(
+            app.status_message,
+            Some(StatusMessage::Info(ref m))
+                if m == "enable --now backup.timer: done, now active (running)."
+        ));
+        assert!(app.app_state == AppState::UnitStatus);
+    }
+
+    #[test]
+    fn saving_under_root_stays_in_the_staged_tree() {
+        let (mut app, dir) = test_app("staged-save", true, Config::default());
+        app.verifier = None;
+        app.scope = Scope::System;
+        app.service_name = "css-staged".to_string();
+        app.start_editing(
+            "[Service]\nExecStart=/bin/true\n\n[Install]\nWantedBy=multi-user.target\n",
+            UnitType::Service,
+        );
+        let path = app.unit_path().unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        app.save_checked();
+        assert_eq!(
+            app.config.post_save_actions,
+            [
+                PostSaveAction::DaemonReload,
+                PostSaveAction::Enable,
+                PostSaveAction::Disable
+            ]
+        );
+        select_action(&mut app, PostSaveAction::Enable);
+        app.run_post_save_action();
+
+        let units = dir.join("root/etc/systemd/system");
+        assert_eq!(path, units.join("css-staged.service"));
+        assert!(path.exists());
+        assert!(units
+            .join("multi-user.target.wants/css-staged.service")
+            .is_symlink());
+        assert!(!Path::new("/etc/systemd/system/css-staged.service").exists());
+        assert!(
+            !Path::new("/etc/systemd/system/multi-user.target.wants/css-staged.service").exists()
+        );
+    }
+
+    #[test]
+    fn control_bindings_work_on_the_template_list() {
+        let config: Config =
+            toml::from_str("[keys]\nquit = \"ctrl-q\"\nmanaged = \"ctrl-m\"").unwrap();
+        let (mut app, _) = test_app("control-bindings", false, config);
+        let ctrl = |ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL);
+        let plain = |ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE);
+
+        assert!(app.handle_keyboard(plain('q')), "q no longer quits");
+        assert!(app.handle_keyboard(ctrl('m')));
+        assert!(app.app_state == AppState::ManagedUnits);
+
+        app.app_state = AppState::SelectServiceTemplate;
+        assert!(!app.handle_keyboard(ctrl('q')));
+    }
+
+    #[test]
+    fn import_screen_keys_follow_the_config() {
+        let manager = RecordingManager::default();
+        let (mut app, _) = app_with_unit(&manager, "rebound.service");
+        app.config = toml::from_str("[keys]\nremove_unit = \"ctrl-d\"").unwrap();
+        app.app_state = AppState::ImportUnit;
+        let ctrl = |ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL);
+        let plain = |ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE);
+
+        assert!(app.handle_keyboard(plain('r')));
+        assert!(app.removal.is_none(), "r no longer removes");
+        assert!(app.handle_keyboard(ctrl('d')));
+        assert!(app.removal.is_some());
+    }
+
+    /// Rooted app holding `name` in the system unit directory.
+    fn app_with_unit(manager: &RecordingManager, name: &str) -> (App, PathBuf) {
+        let (mut app, _) = test_app(name, true, Config::default());
+        app.unit_manager = Box::new(manager.clone());
+        let path = app.unit_dir(Scope::System).unwrap().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "[Service]\nExecStart=/bin/true\n").unwrap();
+        app.import_list =
+            StatefulList::with_items(vec![(name.to_string(), path.display().to_string())]);
+        app.import_list.state.select(Some(0));
+        (app, path)
+    }
+
+    fn record(app: &mut App, path: &Path, backup: Option<PathBuf>) {
+        let entry = LedgerEntry {
+            unit: path.file_name().unwrap().to_string_lossy().to_string(),
+            template: "simple".to_string(),
+            variables: Default::default(),
+            scope: Scope::System,
+            path: path.to_path_buf(),
+            written_at: writer::timestamp(),
+            original_backup: backup,
+            sha256: String::new(),
+        };
+        app.ledger.as_mut().unwrap().record(entry).unwrap();
+    }
+
+    fn write_backup(path: &Path, suffix: &str) -> PathBuf {
+        let backup = PathBuf::from(format!("{}.{suffix}", path.display()));
+        fs::write(&backup, "[Service]\n").unwrap();
+        backup
+    }
+
+    fn planned_steps(app: &App) -> Vec<RemovalStep> {
+        app.removal.as_ref().unwrap().steps.clone()
+    }
+
+    #[test]
+    fn removal_restores_only_what_the_first_write_replaced() {
+        let manager = RecordingManager::default();
+        let (mut app, path) = app_with_unit(&manager, "restore.service");
+        let original = write_backup(&path, "20240101T000000.bak");
+        record(&mut app, &path, Some(original.clone()));
+        let own = write_backup(&path, "20240101T000000.1.bak");
+        record(&mut app, &path, Some(own.clone()));
+
+        app.plan_removal();
+
+        assert_eq!(
+            app.ledger.as_ref().unwrap().entries[0].original_backup,
+            Some(original.clone())
+        );
+        assert_eq!(
+            planned_steps(&app),
+            [
+                RemovalStep::Stop,
+                RemovalStep::RemoveUnit(path.clone()),
+                RemovalStep::RemoveBackups(vec![own]),
+                RemovalStep::RestoreBackup {
+                    backup: original,
+                    path
+                },
+                RemovalStep::DaemonReload,
+            ]
+        );
+    }
+
+    #[test]
+    fn removal_of_a_unit_the_tool_created_restores_nothing() {
+        let manager = RecordingManager::default();
+        let (mut app, path) = app_with_unit(&manager, "created.service");
+        record(&mut app, &path, None);
+        let own = write_backup(&path, "20240101T000000.bak");
+        record(&mut app, &path, Some(own.clone()));
+
+        app.plan_removal();
+
+        assert_eq!(
+            planned_steps(&app),
+            [
+                RemovalStep::Stop,
+                RemovalStep::RemoveUnit(path),
+                RemovalStep::RemoveBackups(vec![own]),
+                RemovalStep::DaemonReload,
+            ]
+        );
+    }
+
+    #[test]
+    fn removal_of_an_unrecorded_unit_keeps_its_backups() {
+        let manager = RecordingManager::default();
+        let (mut app, path) = app_with_unit(&manager, "foreign.service");
+        write_backup(&path, "20240101T000000.bak");
+
+        app.plan_removal();
+
+        assert_eq!(
+            planned_steps(&app),
+            [
+                RemovalStep::Stop,
+                RemovalStep::RemoveUnit(path),
+                RemovalStep::DaemonReload,
+            ]
+        );
+    }
+
+    /// `Verifier` whose systemd-analyze never runs.
+    struct BrokenVerifier;
+
+    impl Verifier for BrokenVerifier {
+        fn verify(
+            &mut self,
+            _scope: Scope,
+            _unit_name: &str,
+            _contents: &str,
+        ) -> io::Result<Vec<Diagnostic>> {
+            Err(io::Error::other("exit status: 1"))
+        }
+    }
+
+    fn save_unverified(block: bool) -> (App, PathBuf) {
+        let (mut app, _) = test_app(&format!("verify-{block}"), true, Config::default());
+        app.verifier = Some(Box::new(BrokenVerifier));
+        app.block_on_verify_errors = block;
+        app.service_name = "unchecked".to_string();
+        app.start_editing("[Service]\nExecStart=/bin/true\n", UnitType::Service);
+        let path = app.unit_path().unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        app.save_checked();
+        (app, path)
+    }
+
+    #[test]
+    fn failed_verify_blocks_the_save() {
+        let (app, path) = save_unverified(true);
+
+        assert!(!path.exists());
+        assert!(app.app_state == AppState::ViewService);
+        assert!(matches!(
+            app.status_message,
+            Some(StatusMessage::Error(ref m))
+                if m == "Not saved, systemd-analyze verify did not run: exit status: 1"
+        ));
+    }
+
+    #[test]
+    fn failed_verify_is_reported_when_not_blocking() {
+        let (app, path) = save_unverified(false);
+
+        assert!(path.exists());
+        assert!(app.app_state == AppState::PostSave);
+        assert!(matches!(
+            app.status_message,
+            Some(StatusMessage::Info(ref m))
+                if m.ends_with("Not verified, systemd-analyze verify did not run: exit status: 1.")
+        ));
+    }
+
+    #[test]
+    fn units_open_in_the_scope_they_belong_to() {
+        let (mut app, dir) = test_app("open-scope", true, Config::default());
+        let global = dir.join("root/etc/systemd/user");
+        fs::create_dir_all(&global).unwrap();
+        fs::write(
+            global.join("sync.service"),
+            "[Service]\nExecStart=/bin/true\n",
+        )
+        .unwrap();
+
+        app.open_unit_file(&global.join("sync.service")).unwrap();
+
+        assert_eq!(app.scope, Scope::GlobalUser);
+        assert_eq!(app.unit_dir(app.scope), Some(global));
     }
 }
@@ -0,0 +1,1494 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Parser, Subcommand};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    app::{App, AppPaths},
+    config::{Config, Verification},
+    diff::{self, DiffLine},
+    escalate, format,
+    install::OfflineManager,
+    ledger::{self, Ledger, LedgerEntry},
+    lint::{LintLevel, Linter},
+    manifest::{self, ManifestUnit},
+    mixin,
+    scope::Scope,
+    spec::{self, UnitSpec},
+    systemctl::{self, PostSaveAction, UnitManager},
+    unitfile::UnitFile,
+    unitpaths,
+    unittype::UnitType,
+    verify::{self, Diagnostic, Severity},
+    writer::{self, WriteOutcome},
+};
+
+/// The command line succeeded.
+pub const EXIT_OK: i32 = 0;
+/// The unit has errors, or writing or systemctl failed.
+pub const EXIT_FAILED: i32 = 1;
+/// Bad arguments or unreadable input, the same code clap uses.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Create systemd units from templates. Without a subcommand the TUI starts.
+///
+/// Exit codes: 0 on success, 1 when the unit has errors or installing it
+/// failed, 2 for bad arguments or unreadable input.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Read and write units below this directory instead of the running
+    /// system, like `systemctl --root`. Enabling creates the [Install]
+    /// symlinks directly.
+    #[arg(long, value_name = "DIR", global = true)]
+    pub root: Option<PathBuf>,
+
+    /// Command that runs the writes and systemctl calls needing root when
+    /// started unprivileged, e.g. "sudo", "pkexec" or "run0". By default the
+    /// first of run0, sudo and pkexec that is installed.
+    #[arg(long, value_name = "COMMAND", global = true)]
+    pub escalate: Option<String>,
+
+    /// Refuse to save units that `systemd-analyze verify` reports errors for,
+    /// instead of only highlighting them.
+    #[arg(long, global = true)]
+    pub block_on_verify_errors: bool,
+
+    /// Print results as JSON.
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Settings from config.toml, with the flags above applied.
+    #[arg(skip)]
+    pub config: Config,
+
+    /// Where templates, mixins and the ledger are kept.
+    #[arg(skip)]
+    pub paths: AppPaths,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List the templates in the template directory.
+    ListTemplates,
+    /// Print a template.
+    Show {
+        /// Template file name, as printed by list-templates.
+        template: String,
+    },
+    /// Fill in a template and print the unit.
+    Render(RenderArgs),
+    /// Print a unit file in a consistent layout: sections in their usual
+    /// order, `Key=Value` without padding and no doubled blank lines.
+    Fmt {
+        /// Unit file to format, standard input if missing or "-".
+        path: Option<PathBuf>,
+        /// Sort the directives of each section by key.
+        #[arg(long)]
+        sort: bool,
+    },
+    /// Fill in a template and install the unit.
+    Install {
+        #[command(flatten)]
+        render: RenderArgs,
+        /// Where to install, the system scope for root and the user scope
+        /// for everyone else by default.
+        #[arg(long, value_enum)]
+        scope: Option<Scope>,
+        /// Enable the unit after installing it.
+        #[arg(long)]
+        enable: bool,
+        /// Start the unit after installing it.
+        #[arg(long)]
+        start: bool,
+        /// Print what would be done without changing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Open the TUI on an existing unit, to save it back in place or as a
+    /// drop-in.
+    Edit {
+        /// Path to a unit file, or a unit name looked up in the systemd
+        /// search paths, e.g. "nginx" or "backup.timer".
+        unit: String,
+    },
+    /// Check a unit file and report its problems.
+    Validate { path: PathBuf },
+    /// List the lint rules with the level each runs at; levels are set in
+    /// the [lint] table of config.toml.
+    ListLintRules,
+    /// Turn a YAML or JSON spec into unit files.
+    Generate {
+        /// Spec file holding one spec or a list of them; JSON if it ends in
+        /// .json, YAML otherwise.
+        spec: PathBuf,
+        /// Write the units into this directory instead of printing them.
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+    },
+    /// Render, check and install every unit of a manifest as one transaction:
+    /// if a write fails, the units already written are put back.
+    Apply {
+        /// YAML or JSON manifest listing templates with their variables or
+        /// inline specs.
+        manifest: PathBuf,
+        /// Print the plan and the diffs against the installed units without
+        /// changing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Describe unit files as a spec, YAML unless --json is given.
+    Export {
+        /// Unit files; a timer given with the service it starts becomes
+        /// part of that service's spec.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// Template file name, as printed by list-templates, or "-" to read the
+    /// template from standard input.
+    template: String,
+    /// Name of the unit, with or without the type suffix.
+    #[arg(long)]
+    name: String,
+    /// Value for the template's `{{KEY}}` placeholder. May be repeated.
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_assignment)]
+    variables: Vec<(String, String)>,
+    /// Mixin to layer onto the template, in order. May be repeated.
+    #[arg(long = "mixin", value_name = "NAME")]
+    mixins: Vec<String>,
+}
+
+fn parse_assignment(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{arg}'"))
+}
+
+/// Why a subcommand failed; decides the exit code.
+#[derive(Debug)]
+enum Failure {
+    Usage(String),
+    Failed(String),
+    /// Reported by the output for stdout, e.g. validate's findings.
+    Reported(String),
+}
+
+impl Failure {
+    fn usage(e: impl Display) -> Self {
+        Failure::Usage(e.to_string())
+    }
+
+    fn failed(e: impl Display) -> Self {
+        Failure::Failed(e.to_string())
+    }
+}
+
+/// Runs a subcommand other than `edit` and returns the exit code.
+pub fn run(cli: &Cli, command: &Command) -> i32 {
+    let result = execute(cli, command);
+    match &result {
+        Ok(output) | Err(Failure::Reported(output)) => print!("{output}"),
+        Err(Failure::Usage(message) | Failure::Failed(message)) => eprintln!("error: {message}"),
+    }
+    exit_code(&result)
+}
+
+fn exit_code(result: &Result<String, Failure>) -> i32 {
+    match result {
+        Ok(_) => EXIT_OK,
+        Err(Failure::Usage(_)) => EXIT_USAGE,
+        Err(Failure::Failed(_) | Failure::Reported(_)) => EXIT_FAILED,
+    }
+}
+
+/// Runs a subcommand other than `edit` and returns what it prints on stdout.
+fn execute(cli: &Cli, command: &Command) -> Result<String, Failure> {
+    match command {
+        Command::ListTemplates => list_templates(cli),
+        Command::Show { template } => show(cli, template),
+        Command::Render(args) => render_command(cli, args),
+        Command::Fmt { path, sort } => fmt(cli, path.as_deref(), *sort),
+        Command::Install {
+            render,
+            scope,
+            enable,
+            start,
+            dry_run,
+        } => install(
+            cli,
+            render,
+            scope
+                .or(cli.config.default_scope)
+                .unwrap_or_else(Scope::default_for_current_user),
+            *enable,
+            *start,
+            *dry_run,
+        ),
+        Command::Validate { path } => validate(cli, path),
+        Command::ListLintRules => list_lint_rules(cli),
+        Command::Generate { spec, output_dir } => generate(cli, spec, output_dir.as_deref()),
+        Command::Export { paths } => export(cli, paths),
+        Command::Apply { manifest, dry_run } => apply(cli, manifest, *dry_run),
+        Command::Edit { .. } => unreachable!("edit starts the TUI"),
+    }
+}
+
+fn json(value: &impl Serialize) -> String {
+    serde_json::to_string_pretty(value).expect("plain data serializes") + "\n"
+}
+
+#[derive(Serialize)]
+struct TemplateInfo<'a> {
+    name: &'a str,
+    #[serde(rename = "type")]
+    unit_type: &'static str,
+}
+
+fn list_templates(cli: &Cli) -> Result<String, Failure> {
+    let (templates, diagnostics) = App::find_service_templates(&cli.paths.config_dir);
+    for diagnostic in diagnostics {
+        eprintln!("warning: {diagnostic}");
+    }
+
+    if cli.json {
+        let infos: Vec<_> = templates
+            .iter()
+            .map(|(name, _)| TemplateInfo {
+                name,
+                unit_type: UnitType::from_file_name(name)
+                    .unwrap_or(UnitType::Service)
+                    .suffix(),
+            })
+            .collect();
+        Ok(json(&infos))
+    } else {
+        Ok(templates
+            .iter()
+            .map(|(name, _)| format!("{name}\n"))
+            .collect())
+    }
+}
+
+fn find_template(cli: &Cli, name: &str) -> Result<String, Failure> {
+    let (templates, _) = App::find_service_templates(&cli.paths.config_dir);
+    templates
+        .into_iter()
+        .find(|(template, _)| template == name)
+        .map(|(_, contents)| contents)
+        .ok_or_else(|| Failure::Usage(format!("no template named {name}, see list-templates")))
+}
+
+fn show(cli: &Cli, name: &str) -> Result<String, Failure> {
+    let contents = find_template(cli, name)?;
+    if cli.json {
+        Ok(json(&BTreeMap::from([
+            ("name", name),
+            ("contents", &contents),
+        ])))
+    } else {
+        Ok(contents)
+    }
+}
+
+/// A template filled in from the command line.
+struct Rendered {
+    unit_name: String,
+    unit_type: UnitType,
+    template: String,
+    contents: String,
+}
+
+fn render(cli: &Cli, args: &RenderArgs) -> Result<Rendered, Failure> {
+    let variables: BTreeMap<_, _> = args.variables.iter().cloned().collect();
+    let rendered = render_template(cli, &args.template, &args.name, &variables, &args.mixins)?;
+    if let Some(missing) = missing_variable(&rendered.contents) {
+        return Err(Failure::Usage(format!(
+            "no value for {{{{{missing}}}}}, pass --set {missing}=..."
+        )));
+    }
+    Ok(rendered)
+}
+
+/// Fills in `template` with its mixins layered on; placeholders without a
+/// value are left in place. A template named "-" is read from standard input
+/// and takes its unit type from `name`.
+fn render_template(
+    cli: &Cli,
+    template_name: &str,
+    name: &str,
+    variables: &BTreeMap<String, String>,
+    mixins: &[String],
+) -> Result<Rendered, Failure> {
+    let (template, unit_type) = if template_name == "-" {
+        let template = read_input(None)?;
+        (template, UnitType::from_file_name(name).unwrap_or_default())
+    } else {
+        let unit_type = UnitType::from_file_name(template_name).unwrap_or_default();
+        (find_template(cli, template_name)?, unit_type)
+    };
+
+    let available = mixin::find_mixins(&cli.paths.mixins_dir());
+    let mixins = mixins
+        .iter()
+        .map(|name| {
+            available
+                .iter()
+                .find(|(mixin, _)| mixin == name)
+                .map(|(_, contents)| contents.as_str())
+                .ok_or_else(|| Failure::Usage(format!("no mixin named {name}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let merged = mixin::apply_mixins(&template, &mixins)
+        .map_err(|e| Failure::Usage(format!("{template_name}: {e}")))?;
+
+    Ok(Rendered {
+        unit_name: unit_type.file_name(name),
+        unit_type,
+        contents: ledger::fill_variables(&merged, variables),
+        template,
+    })
+}
+
+/// The first `{{KEY}}` placeholder left in `contents`.
+fn missing_variable(contents: &str) -> Option<String> {
+    let placeholder = Regex::new(r"\{\{(\w+)\}\}").unwrap();
+    placeholder
+        .captures(contents)
+        .map(|captures| captures[1].to_string())
+}
+
+/// Contents of `path`, or of standard input for `None` or "-".
+fn read_input(path: Option<&Path>) -> Result<String, Failure> {
+    match path {
+        Some(path) if path != Path::new("-") => fs::read_to_string(path)
+            .map_err(|e| Failure::Usage(format!("unable to read {}: {e}", path.display()))),
+        _ => io::read_to_string(io::stdin())
+            .map_err(|e| Failure::Usage(format!("unable to read standard input: {e}"))),
+    }
+}
+
+fn fmt(cli: &Cli, path: Option<&Path>, sort: bool) -> Result<String, Failure> {
+    let contents = read_input(path)?;
+    let name = path
+        .filter(|path| *path != Path::new("-"))
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "<stdin>".to_string());
+    let unit = UnitFile::parse(&contents)
+        .map_err(|e| Failure::Usage(format!("{name}:{}: {}", e.line, e.message)))?;
+    let formatted = format::normalize(&unit, sort).to_string();
+
+    if cli.json {
+        Ok(json(&BTreeMap::from([("contents", &formatted)])))
+    } else {
+        Ok(formatted)
+    }
+}
+
+fn render_command(cli: &Cli, args: &RenderArgs) -> Result<String, Failure> {
+    let rendered = render(cli, args)?;
+    if cli.json {
+        Ok(json(&BTreeMap::from([
+            ("unit", &rendered.unit_name),
+            ("contents", &rendered.contents),
+        ])))
+    } else {
+        Ok(rendered.contents)
+    }
+}
+
+#[derive(Serialize)]
+struct InstallReport {
+    unit: String,
+    path: PathBuf,
+    backup: Option<PathBuf>,
+    actions: Vec<&'static str>,
+    dry_run: bool,
+}
+
+fn install(
+    cli: &Cli,
+    args: &RenderArgs,
+    scope: Scope,
+    enable: bool,
+    start: bool,
+    dry_run: bool,
+) -> Result<String, Failure> {
+    let rendered = render(cli, args)?;
+    let problems = check(
+        cli,
+        scope,
+        &rendered.unit_name,
+        &rendered.contents,
+        rendered.unit_type,
+    );
+    if (cli.config.verify == Verification::Block)
+        && problems.iter().any(|p| p.severity == Severity::Error)
+    {
+        return Err(Failure::Reported(problems_text(
+            cli,
+            &PathBuf::from(&rendered.unit_name),
+            &problems,
+        )));
+    }
+
+    if cli.root.is_some() && scope == Scope::User {
+        return Err(Failure::usage(
+            "the user scope is not available with --root",
+        ));
+    }
+    if cli.root.is_some() && start {
+        return Err(Failure::usage(
+            "--start is not available with --root, nothing runs in the staged tree",
+        ));
+    }
+    let dir = scope
+        .unit_dir()
+        .ok_or_else(|| Failure::usage("neither $XDG_CONFIG_HOME nor $HOME is set"))?;
+    let path = unitpaths::rebase(cli.root.as_deref(), &dir).join(&rendered.unit_name);
+
+    // systemctl enable reloads by itself; start needs the new unit loaded.
+    let mut actions = vec![];
+    if start && !enable && scope != Scope::GlobalUser {
+        actions.push(PostSaveAction::DaemonReload);
+    }
+    match (enable, start) {
+        (true, true) => actions.push(PostSaveAction::EnableNow),
+        (true, false) => actions.push(PostSaveAction::Enable),
+        (false, true) => actions.push(PostSaveAction::Start),
+        (false, false) => (),
+    }
+
+    let mut report = InstallReport {
+        unit: rendered.unit_name.clone(),
+        path: path.clone(),
+        backup: None,
+        actions: actions.iter().map(PostSaveAction::label).collect(),
+        dry_run,
+    };
+
+    if !dry_run {
+        let mut escalator = escalate::escalator(cli.config.escalate.as_deref(), false);
+        let outcome = writer::write_unit_with(escalator.as_deref_mut(), &path, &rendered.contents)
+            .map_err(|e| Failure::Failed(format!("unable to write {}: {e}", path.display())))?;
+        if let Err(e) = record(cli, &rendered, &args.template, scope, &outcome) {
+            eprintln!(
+                "warning: unable to record {} as managed: {e}",
+                rendered.unit_name
+            );
+        }
+        report.backup = outcome.backup;
+
+        let mut manager: Box<dyn UnitManager> = match &cli.root {
+            Some(root) => Box::new(OfflineManager {
+                root: Some(root.clone()),
+                escalator,
+            }),
+            None => systemctl::default_manager(escalator),
+        };
+        for action in &actions {
+            action
+                .run(manager.as_mut(), scope, &rendered.unit_name)
+                .map_err(Failure::failed)?;
+        }
+    }
+
+    if cli.json {
+        return Ok(json(&report));
+    }
+    let mut out = String::new();
+    let verb = if dry_run { "Would write" } else { "Wrote" };
+    writeln!(out, "{verb} {}", report.path.display()).unwrap();
+    if let Some(backup) = &report.backup {
+        writeln!(out, "Kept previous version as {}", backup.display()).unwrap();
+    }
+    for action in &actions {
+        let verb = if dry_run { "Would run" } else { "Ran" };
+        match action {
+            PostSaveAction::DaemonReload => {
+                writeln!(out, "{verb} systemctl {}", action.label()).unwrap()
+            }
+            _ => writeln!(out, "{verb} systemctl {} {}", action.label(), report.unit).unwrap(),
+        }
+    }
+    if dry_run {
+        write!(out, "\n{}", rendered.contents).unwrap();
+    }
+    Ok(out)
+}
+
+fn record(
+    cli: &Cli,
+    rendered: &Rendered,
+    template: &str,
+    scope: Scope,
+    outcome: &WriteOutcome,
+) -> std::io::Result<()> {
+    let mut ledger = Ledger::load(&cli.paths.ledger)?;
+    ledger.record(LedgerEntry {
+        unit: rendered.unit_name.clone(),
+        template: template.to_string(),
+        variables: ledger::template_variables(&rendered.template, &rendered.contents),
+        scope,
+        path: outcome.path.clone(),
+        written_at: writer::timestamp(),
+        original_backup: outcome.backup.clone(),
+        sha256: ledger::content_hash(&rendered.contents),
+    })
+}
+
+/// Parse errors, unit type checks, lint findings and, if installed,
+/// systemd-analyze verify.
+fn check(
+    cli: &Cli,
+    scope: Scope,
+    unit_name: &str,
+    contents: &str,
+    unit_type: UnitType,
+) -> Vec<Diagnostic> {
+    let unit = match UnitFile::parse(contents) {
+        Ok(unit) => unit,
+        Err(e) => {
+            return vec![Diagnostic {
+                line: Some(e.line),
+                severity: Severity::Error,
+                message: e.message,
+                rule: None,
+            }]
+        }
+    };
+
+    let mut problems: Vec<_> = unit_type
+        .validate(&unit)
+        .into_iter()
+        .map(|message| Diagnostic {
+            line: None,
+            severity: Severity::Warning,
+            message,
+            rule: None,
+        })
+        .collect();
+    problems.extend(Linter::new(cli.config.lint.clone()).lint(contents, unit_type));
+    if cli.config.verify == Verification::Off {
+        return problems;
+    }
+    if let Some(mut verifier) = verify::default_verifier() {
+        match verifier.verify(scope, unit_name, contents) {
+            Ok(diagnostics) => problems.extend(diagnostics),
+            Err(e) => eprintln!("warning: unable to run systemd-analyze verify: {e}"),
+        }
+    }
+    problems
+}
+
+#[derive(Serialize)]
+struct ValidationReport<'a> {
+    path: &'a Path,
+    valid: bool,
+    problems: &'a [Diagnostic],
+}
+
+fn problems_text(cli: &Cli, path: &Path, problems: &[Diagnostic]) -> String {
+    let valid = !problems.iter().any(|p| p.severity == Severity::Error);
+    if cli.json {
+        return json(&ValidationReport {
+            path,
+            valid,
+            problems,
+        });
+    }
+    let mut out = String::new();
+    for problem in problems {
+        let location = match problem.line {
+            Some(line) => format!("{}:{line}", path.display()),
+            None => path.display().to_string(),
+        };
+        let severity = match problem.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match problem.rule {
+            Some(rule) => writeln!(out, "{location}: {severity}[{rule}]: {}", problem.message),
+            None => writeln!(out, "{location}: {severity}: {}", problem.message),
+        }
+        .unwrap();
+    }
+    out
+}
+
+fn validate(cli: &Cli, path: &Path) -> Result<String, Failure> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Failure::Usage(format!("unable to read {}: {e}", path.display())))?;
+    let unit_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let unit_type = UnitType::from_file_name(&unit_name)
+        .ok_or_else(|| Failure::Usage(format!("{unit_name} is not a known unit type")))?;
+    let scope = Scope::ALL
+        .into_iter()
+        .find(|scope| scope.unit_dir().as_deref() == path.parent())
+        .unwrap_or(Scope::System);
+
+    let problems = check(cli, scope, &unit_name, &contents, unit_type);
+    let out = problems_text(cli, path, &problems);
+    if problems.iter().any(|p| p.severity == Severity::Error) {
+        return Err(Failure::Reported(out));
+    }
+    Ok(out)
+}
+
+#[derive(Serialize)]
+struct LintRuleInfo {
+    id: &'static str,
+    level: LintLevel,
+    summary: &'static str,
+}
+
+fn list_lint_rules(cli: &Cli) -> Result<String, Failure> {
+    let linter = Linter::new(cli.config.lint.clone());
+    if cli.json {
+        let infos: Vec<_> = linter
+            .rules()
+            .map(|(rule, level)| LintRuleInfo {
+                id: rule.id(),
+                level,
+                summary: rule.summary(),
+            })
+            .collect();
+        return Ok(json(&infos));
+    }
+    let mut out = String::new();
+    for (rule, level) in linter.rules() {
+        let level = match level {
+            LintLevel::Off => "off",
+            LintLevel::Warning => "warning",
+            LintLevel::Error => "error",
+        };
+        writeln!(out, "{:<30} {level:<8} {}", rule.id(), rule.summary()).unwrap();
+    }
+    Ok(out)
+}
+
+#[derive(Serialize)]
+struct GeneratedUnit {
+    unit: String,
+    contents: String,
+}
+
+fn generate(cli: &Cli, path: &Path, output_dir: Option<&Path>) -> Result<String, Failure> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Failure::Usage(format!("unable to read {}: {e}", path.display())))?;
+    let specs = spec::parse_specs(path, &text)
+        .map_err(|e| Failure::Usage(format!("{}: {e}", path.display())))?;
+
+    let mixins = mixin::find_mixins(&cli.paths.mixins_dir());
+    let mut units = vec![];
+    for spec in &specs {
+        for (unit, contents) in spec.to_units(&mixins).map_err(Failure::Usage)? {
+            units.push(GeneratedUnit { unit, contents });
+        }
+    }
+
+    let mut out = String::new();
+    if let Some(dir) = output_dir {
+        fs::create_dir_all(dir)
+            .map_err(|e| Failure::Failed(format!("unable to create {}: {e}", dir.display())))?;
+        for unit in &units {
+            let path = dir.join(&unit.unit);
+            fs::write(&path, &unit.contents)
+                .map_err(|e| Failure::Failed(format!("unable to write {}: {e}", path.display())))?;
+            if !cli.json {
+                writeln!(out, "Wrote {}", path.display()).unwrap();
+            }
+        }
+    }
+
+    if cli.json {
+        out = json(&units);
+    } else if output_dir.is_none() {
+        for (i, unit) in units.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            write!(out, "# {}\n{}", unit.unit, unit.contents).unwrap();
+        }
+    }
+    Ok(out)
+}
+
+fn export(cli: &Cli, paths: &[PathBuf]) -> Result<String, Failure> {
+    let mut units = vec![];
+    for path in paths {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| Failure::Usage(format!("unable to read {}: {e}", path.display())))?;
+        let unit = UnitFile::parse(&contents)
+            .map_err(|e| Failure::Usage(format!("{}:{}: {}", path.display(), e.line, e.message)))?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        units.push((file_name, unit));
+    }
+
+    // Timers go last so they can join the spec of the service they start.
+    let mixins = mixin::find_mixins(&cli.paths.mixins_dir());
+    let (timers, others): (Vec<_>, Vec<_>) = units
+        .iter()
+        .partition(|(name, _)| UnitType::from_file_name(name) == Some(UnitType::Timer));
+    let mut specs: Vec<UnitSpec> = others
+        .iter()
+        .map(|(name, unit)| UnitSpec::from_unit(name, unit, &mixins))
+        .collect();
+    for (name, timer) in timers {
+        if !specs.iter_mut().any(|spec| spec.attach_timer(name, timer)) {
+            specs.push(UnitSpec::from_unit(name, timer, &mixins));
+        }
+    }
+
+    if cli.json {
+        match specs.as_slice() {
+            [spec] => Ok(json(spec)),
+            specs => Ok(json(&specs)),
+        }
+    } else {
+        let yaml = match specs.as_slice() {
+            [spec] => serde_yaml::to_string(spec),
+            specs => serde_yaml::to_string(specs),
+        };
+        Ok(yaml.expect("plain data serializes"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PlanStatus {
+    New,
+    Changed,
+    Unchanged,
+}
+
+/// One unit of an applied manifest.
+#[derive(Serialize)]
+struct PlannedUnit {
+    unit: String,
+    scope: Scope,
+    path: PathBuf,
+    status: PlanStatus,
+    /// Unified diff against the installed unit.
+    diff: Vec<String>,
+    problems: Vec<Diagnostic>,
+    actions: Vec<&'static str>,
+    /// The actions that ran, in order.
+    ran: Vec<&'static str>,
+    /// Why the action after the last one that ran failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    backup: Option<PathBuf>,
+    #[serde(skip)]
+    post_save: Vec<PostSaveAction>,
+    #[serde(skip)]
+    rendered: Rendered,
+    /// Template name for the ledger.
+    #[serde(skip)]
+    source: String,
+}
+
+#[derive(Serialize)]
+struct ApplyReport<'a> {
+    applied: bool,
+    units: &'a [PlannedUnit],
+}
+
+/// The units one manifest entry renders to. A spec's enable and start go to
+/// its timers if it has any, to the unit itself otherwise.
+fn render_entry(
+    cli: &Cli,
+    entry: &ManifestUnit,
+    manifest_name: &str,
+    mixins: &[(String, String)],
+) -> Result<Vec<(Rendered, String, bool)>, Failure> {
+    if let (Some(template), Some(name)) = (&entry.template, &entry.name) {
+        let rendered = render_template(cli, template, name, &entry.variables, &entry.mixins)?;
+        if let Some(missing) = missing_variable(&rendered.contents) {
+            return Err(Failure::Usage(format!(
+                "{}: no value for {{{{{missing}}}}} in variables",
+                rendered.unit_name
+            )));
+        }
+        return Ok(vec![(rendered, template.clone(), true)]);
+    }
+
+    let spec = entry.spec.as_ref().expect("checked by parse_manifest");
+    let units = spec.to_units(mixins).map_err(Failure::Usage)?;
+    let has_timers = units.len() > 1;
+    Ok(units
+        .into_iter()
+        .enumerate()
+        .map(|(i, (unit_name, contents))| {
+            let rendered = Rendered {
+                unit_type: UnitType::from_file_name(&unit_name).unwrap_or_default(),
+                unit_name,
+                template: String::new(),
+                contents,
+            };
+            (rendered, manifest_name.to_string(), !has_timers || i > 0)
+        })
+        .collect())
+}
+
+fn diff_text(lines: &[DiffLine]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| match line {
+            DiffLine::Hunk(text) => text.clone(),
+            DiffLine::Context(text) => format!(" {text}"),
+            DiffLine::Removed(text) => format!("-{text}"),
+            DiffLine::Added(text) => format!("+{text}"),
+        })
+        .collect()
+}
+
+fn apply(cli: &Cli, path: &Path, dry_run: bool) -> Result<String, Failure> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| Failure::Usage(format!("unable to read {}: {e}", path.display())))?;
+    let manifest = manifest::parse_manifest(path, &text)
+        .map_err(|e| Failure::Usage(format!("{}: {e}", path.display())))?;
+    let manifest_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let default_scope = manifest
+        .scope
+        .or(cli.config.default_scope)
+        .unwrap_or_else(Scope::default_for_current_user);
+    let mixins = mixin::find_mixins(&cli.paths.mixins_dir());
+
+    // Render and check everything before touching the disk.
+    let mut plan: Vec<PlannedUnit> = vec![];
+    for (i, entry) in manifest.units.iter().enumerate() {
+        let scope = entry.scope.unwrap_or(default_scope);
+        if cli.root.is_some() && entry.start {
+            return Err(Failure::Usage(format!(
+                "{}: unit {}: start is not available with --root, nothing runs in the staged tree",
+                path.display(),
+                i + 1
+            )));
+        }
+        if cli.root.is_some() && scope == Scope::User {
+            return Err(Failure::usage(
+                "the user scope is not available with --root",
+            ));
+        }
+        let dir = scope
+            .unit_dir()
+            .ok_or_else(|| Failure::usage("neither $XDG_CONFIG_HOME nor $HOME is set"))?;
+        let dir = unitpaths::rebase(cli.root.as_deref(), &dir);
+
+        for (rendered, source, activate) in render_entry(cli, entry, &manifest_name, &mixins)? {
+            let path = dir.join(&rendered.unit_name);
+            if plan.iter().any(|planned| planned.path == path) {
+                return Err(Failure::Usage(format!(
+                    "{} is in the manifest more than once",
+                    rendered.unit_name
+                )));
+            }
+
+            let (status, diff) = match fs::read_to_string(&path) {
+                Ok(installed) if installed == rendered.contents => (PlanStatus::Unchanged, vec![]),
+                Ok(installed) => (
+                    PlanStatus::Changed,
+                    diff_text(&diff::unified_diff(&installed, &rendered.contents)),
+                ),
+                Err(_) => (PlanStatus::New, vec![]),
+            };
+            let actions = match (activate && entry.enable, activate && entry.start) {
+                (true, true) => vec![PostSaveAction::EnableNow],
+                (true, false) => vec![PostSaveAction::Enable],
+                (false, true) => vec![PostSaveAction::Start],
+                (false, false) => vec![],
+            };
+            plan.push(PlannedUnit {
+                problems: check(
+                    cli,
+                    scope,
+                    &rendered.unit_name,
+                    &rendered.contents,
+                    rendered.unit_type,
+                ),
+                unit: rendered.unit_name.clone(),
+                scope,
+                path,
+                status,
+                diff,
+                actions: actions.iter().map(PostSaveAction::label).collect(),
+                ran: vec![],
+                error: None,
+                backup: None,
+                post_save: actions,
+                rendered,
+                source,
+            });
+        }
+    }
+
+    let blocked = (cli.config.verify == Verification::Block)
+        && plan
+            .iter()
+            .flat_map(|planned| &planned.problems)
+            .any(|p| p.severity == Severity::Error);
+    if blocked || dry_run {
+        let out = plan_text(cli, &plan, false);
+        return if blocked {
+            Err(Failure::Reported(out))
+        } else {
+            Ok(out)
+        };
+    }
+
+    let writes: Vec<(PathBuf, String)> = plan
+        .iter()
+        .filter(|planned| planned.status != PlanStatus::Unchanged)
+        .map(|planned| (planned.path.clone(), planned.rendered.contents.clone()))
+        .collect();
+    let mut escalator = escalate::escalator(cli.config.escalate.as_deref(), false);
+    let outcomes = writer::write_all_with(escalator.as_deref_mut(), &writes)
+        .map_err(|e| Failure::Failed(format!("{e}; no unit of {} was changed", path.display())))?;
+    for outcome in outcomes {
+        let Some(planned) = plan.iter_mut().find(|p| p.path == outcome.path) else {
+            continue;
+        };
+        if let Err(e) = record(
+            cli,
+            &planned.rendered,
+            &planned.source,
+            planned.scope,
+            &outcome,
+        ) {
+            eprintln!("warning: unable to record {} as managed: {e}", planned.unit);
+        }
+        planned.backup = outcome.backup;
+    }
+
+    let mut manager: Box<dyn UnitManager> = match &cli.root {
+        Some(root) => Box::new(OfflineManager {
+            root: Some(root.clone()),
+            escalator,
+        }),
+        None => systemctl::default_manager(escalator),
+    };
+    let mut reloaded = vec![];
+    for planned in &plan {
+        let scope = planned.scope;
+        if planned.status != PlanStatus::Unchanged
+            && scope != Scope::GlobalUser
+            && !reloaded.contains(&scope)
+        {
+            manager.daemon_reload(scope).map_err(|e| {
+                Failure::Failed(format!(
+                    "{e}; the units of {} were written but not loaded",
+                    path.display()
+                ))
+            })?;
+            reloaded.push(scope);
+        }
+    }
+    // A failed action only stops the ones after it for the same unit.
+    for planned in &mut plan {
+        for action in &planned.post_save {
+            if let Err(e) = action.run(manager.as_mut(), planned.scope, &planned.unit) {
+                planned.error = Some(e.to_string());
+                break;
+            }
+            planned.ran.push(action.label());
+        }
+    }
+
+    let out = plan_text(cli, &plan, true);
+    if plan.iter().any(|planned| planned.error.is_some()) {
+        return Err(Failure::Reported(out));
+    }
+    Ok(out)
+}
+
+/// The plan, as done if `applied` and as what would be done otherwise.
+fn plan_text(cli: &Cli, plan: &[PlannedUnit], applied: bool) -> String {
+    if cli.json {
+        return json(&ApplyReport {
+            applied,
+            units: plan,
+        });
+    }
+    let dry_run = !applied;
+    let mut out = String::new();
+    for planned in plan {
+        let status = match (planned.status, dry_run) {
+            (PlanStatus::Unchanged, _) => "Unchanged",
+            (PlanStatus::New, true) => "Would create",
+            (PlanStatus::Changed, true) => "Would change",
+            (PlanStatus::New, false) => "Created",
+            (PlanStatus::Changed, false) => "Changed",
+        };
+        writeln!(out, "{status} {}", planned.path.display()).unwrap();
+        for line in &planned.diff {
+            writeln!(out, "    {line}").unwrap();
+        }
+        out += &problems_text(cli, &planned.path, &planned.problems);
+        if dry_run {
+            for action in &planned.actions {
+                writeln!(out, "Would run systemctl {action} {}", planned.unit).unwrap();
+            }
+        }
+        for action in &planned.ran {
+            writeln!(out, "Ran systemctl {action} {}", planned.unit).unwrap();
+        }
+        if let Some(e) = &planned.error {
+            let action = planned.actions[planned.ran.len()];
+            writeln!(out, "Failed systemctl {action} {}: {e}", planned.unit).unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const TEMPLATE: &str = "[Unit]\nDescription=Web\n\n[Service]\n\
+                            ExecStart=/bin/true --port {{PORT}}\n\n\
+                            [Install]\nWantedBy=multi-user.target\n";
+
+    /// A fresh directory for `test` whose config holds the template web.service.
+    fn test_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("css-cli-{}-{test}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::write(dir.join("config/web.service"), TEMPLATE).unwrap();
+        dir
+    }
+
+    /// Runs the command line `args` with the templates and ledger of `dir`.
+    fn execute_in(dir: &Path, args: &[&str]) -> Result<String, Failure> {
+        let mut cli = Cli::try_parse_from(["create-systemd-service"].iter().chain(args)).unwrap();
+        cli.config.verify = Verification::Off;
+        cli.paths = AppPaths {
+            config_dir: dir.join("config"),
+            ledger: dir.join("units.json"),
+        };
+        execute(&cli, cli.command.as_ref().unwrap())
+    }
+
+    fn parsed(output: Result<String, Failure>) -> Value {
+        serde_json::from_str(&output.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn render_fills_in_the_template() {
+        let dir = test_dir("render");
+        let args = [
+            "render",
+            "web.service",
+            "--name",
+            "shop",
+            "--set",
+            "PORT=8080",
+        ];
+
+        let output = execute_in(&dir, &args).unwrap();
+        assert_eq!(output, TEMPLATE.replace("{{PORT}}", "8080"));
+        assert_eq!(
+            parsed(execute_in(&dir, &[&["--json"], &args[..]].concat())),
+            json!({ "unit": "shop.service", "contents": output })
+        );
+    }
+
+    #[test]
+    fn bad_input_exits_with_the_usage_code() {
+        let dir = test_dir("usage");
+
+        let missing = execute_in(&dir, &["render", "web.service", "--name", "shop"]);
+        assert!(
+            matches!(&missing, Err(Failure::Usage(m)) if m == "no value for {{PORT}}, pass --set PORT=..."),
+            "{missing:?}"
+        );
+        assert_eq!(exit_code(&missing), EXIT_USAGE);
+
+        let unknown = execute_in(&dir, &["show", "db.service"]);
+        assert_eq!(exit_code(&unknown), EXIT_USAGE);
+        let unreadable = execute_in(&dir, &["validate", "/nonexistent/web.service"]);
+        assert_eq!(exit_code(&unreadable), EXIT_USAGE);
+    }
+
+    #[test]
+    fn validate_reports_errors_with_the_failure_code() {
+        let dir = test_dir("validate");
+        let good = dir.join("good.service");
+        fs::write(&good, "[Service]\nExecStart=/bin/true\n").unwrap();
+        let bad = dir.join("bad.service");
+        fs::write(&bad, "[Service]\nExecStart=/bin/true\nnot a directive\n").unwrap();
+
+        let result = execute_in(&dir, &["validate", good.to_str().unwrap()]);
+        assert_eq!(exit_code(&result), EXIT_OK);
+
+        let result = execute_in(&dir, &["validate", bad.to_str().unwrap()]);
+        assert_eq!(exit_code(&result), EXIT_FAILED);
+        let Err(Failure::Reported(output)) = result else {
+            panic!("{result:?}");
+        };
+        assert_eq!(
+            output,
+            format!(
+                "{}:3: error: expected Key=Value, found \"not a directive\"\n",
+                bad.display()
+            )
+        );
+
+        let Err(Failure::Reported(output)) =
+            execute_in(&dir, &["--json", "validate", bad.to_str().unwrap()])
+        else {
+            panic!("validate --json of a broken unit succeeded");
+        };
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap(),
+            json!({
+                "path": bad,
+                "valid": false,
+                "problems": [{
+                    "line": 3,
+                    "severity": "error",
+                    "message": "expected Key=Value, found \"not a directive\"",
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn install_dry_run_changes_nothing() {
+        let dir = test_dir("install-dry-run");
+        let root = dir.join("root");
+        let args = [
+            "--root",
+            root.to_str().unwrap(),
+            "install",
+            "web.service",
+            "--name",
+            "shop",
+            "--set",
+            "PORT=8080",
+            "--scope",
+            "system",
+            "--enable",
+            "--dry-run",
+        ];
+
+        let output = execute_in(&dir, &args).unwrap();
+        let path = root.join("etc/systemd/system/shop.service");
+        assert_eq!(
+            output,
+            format!(
+                "Would write {}\nWould run systemctl enable shop.service\n\n{}",
+                path.display(),
+                TEMPLATE.replace("{{PORT}}", "8080")
+            )
+        );
+        assert_eq!(
+            parsed(execute_in(&dir, &[&["--json"], &args[..]].concat())),
+            json!({
+                "unit": "shop.service",
+                "path": path,
+                "backup": null,
+                "actions": ["enable"],
+                "dry_run": true,
+            })
+        );
+        assert!(!root.exists());
+        assert!(!dir.join("units.json").exists());
+    }
+
+    /// Writes the manifest `text` into `dir` and returns its path as given
+    /// on the command line.
+    fn manifest(dir: &Path, text: &str) -> String {
+        let path = dir.join("manifest.yaml");
+        fs::write(&path, text).unwrap();
+        path.display().to_string()
+    }
+
+    fn rendered(port: &str) -> String {
+        TEMPLATE.replace("{{PORT}}", port)
+    }
+
+    #[test]
+    fn apply_dry_run_plans_without_changing_anything() {
+        let dir = test_dir("apply-dry-run");
+        let root = dir.join("root");
+        let units = root.join("etc/systemd/system");
+        fs::create_dir_all(&units).unwrap();
+        fs::write(units.join("api.service"), rendered("1")).unwrap();
+        fs::write(units.join("db.service"), rendered("5432")).unwrap();
+        let manifest = manifest(
+            &dir,
+            "scope: system\nunits:\n\
+             - { template: web.service, name: api, variables: { PORT: '8080' }, enable: true }\n\
+             - { template: web.service, name: db, variables: { PORT: '5432' } }\n\
+             - { template: web.service, name: shop, variables: { PORT: '80' } }\n",
+        );
+        let args = [
+            "--root",
+            root.to_str().unwrap(),
+            "apply",
+            &manifest,
+            "--dry-run",
+        ];
+
+        let output = execute_in(&dir, &args).unwrap();
+        let summary: Vec<_> = output
+            .lines()
+            .filter(|line| !line.starts_with("    "))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                format!("Would change {}", units.join("api.service").display()),
+                "Would run systemctl enable api.service".to_string(),
+                format!("Unchanged {}", units.join("db.service").display()),
+                format!("Would create {}", units.join("shop.service").display()),
+            ]
+        );
+        assert!(output.contains("\n    -ExecStart=/bin/true --port 1\n"));
+        assert!(output.contains("\n    +ExecStart=/bin/true --port 8080\n"));
+
+        let report = parsed(execute_in(&dir, &[&["--json"], &args[..]].concat()));
+        assert_eq!(report["applied"], json!(false));
+        let statuses: Vec<_> = report["units"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|unit| unit["status"].clone())
+            .collect();
+        assert_eq!(
+            statuses,
+            [json!("changed"), json!("unchanged"), json!("new")]
+        );
+
+        assert_eq!(
+            fs::read_to_string(units.join("api.service")).unwrap(),
+            rendered("1")
+        );
+        assert!(!units.join("shop.service").exists());
+        assert!(!units.join("multi-user.target.wants").exists());
+    }
+
+    #[test]
+    fn apply_reports_the_actions_it_ran() {
+        let dir = test_dir("apply");
+        let root = dir.join("root");
+        let manifest = manifest(
+            &dir,
+            "scope: system\nunits:\n\
+             - { template: web.service, name: shop, variables: { PORT: '80' }, enable: true }\n",
+        );
+
+        let output = execute_in(
+            &dir,
+            &["--root", root.to_str().unwrap(), "apply", &manifest],
+        );
+
+        let units = root.join("etc/systemd/system");
+        assert_eq!(
+            output.unwrap(),
+            format!(
+                "Created {}\nRan systemctl enable shop.service\n",
+                units.join("shop.service").display()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(units.join("shop.service")).unwrap(),
+            rendered("80")
+        );
+        assert!(units
+            .join("multi-user.target.wants/shop.service")
+            .is_symlink());
+        assert!(dir.join("units.json").exists());
+    }
+
+    #[test]
+    fn apply_reports_failed_actions_with_the_rest() {
+        let dir = test_dir("apply-failed-action");
+        let root = dir.join("root");
+        let wants = root.join("etc/systemd/system/multi-user.target.wants");
+        fs::create_dir_all(&wants).unwrap();
+        std::os::unix::fs::symlink(
+            "/etc/systemd/system/other.service",
+            wants.join("shop.service"),
+        )
+        .unwrap();
+        let manifest = manifest(
+            &dir,
+            "scope: system\nunits:\n\
+             - { template: web.service, name: shop, variables: { PORT: '80' }, enable: true }\n\
+             - { template: web.service, name: api, variables: { PORT: '8080' }, enable: true }\n",
+        );
+        let args = ["--root", root.to_str().unwrap(), "apply", &manifest];
+
+        let result = execute_in(&dir, &args);
+
+        assert_eq!(exit_code(&result), EXIT_FAILED);
+        let Err(Failure::Reported(output)) = result else {
+            panic!("{result:?}");
+        };
+        let units = root.join("etc/systemd/system");
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!("Created {}", units.join("shop.service").display())
+        );
+        assert!(lines[1].starts_with("Failed systemctl enable shop.service: "));
+        assert_eq!(
+            lines[2..],
+            [
+                format!("Created {}", units.join("api.service").display()),
+                "Ran systemctl enable api.service".to_string(),
+            ]
+        );
+
+        // Run again for the report; the units are unchanged by now.
+        let Err(Failure::Reported(output)) = execute_in(&dir, &[&["--json"], &args[..]].concat())
+        else {
+            panic!("the failing link is still there");
+        };
+        let report: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(report["applied"], json!(true));
+        assert_eq!(report["units"][0]["status"], json!("unchanged"));
+        assert_eq!(report["units"][0]["ran"], json!([]));
+        assert!(report["units"][0]["error"]
+            .as_str()
+            .unwrap()
+            .ends_with("already points at /etc/systemd/system/other.service"));
+        assert_eq!(report["units"][1]["ran"], json!(["enable"]));
+        assert!(report["units"][1].get("error").is_none());
+        assert!(wants.join("api.service").is_symlink());
+    }
+
+    #[test]
+    fn units_named_twice_are_refused() {
+        let dir = test_dir("apply-twice");
+        let root = dir.join("root");
+        let manifest = manifest(
+            &dir,
+            "scope: system\nunits:\n\
+             - { template: web.service, name: shop, variables: { PORT: '80' } }\n\
+             - spec: { name: shop, exec: /usr/bin/shop }\n",
+        );
+
+        let result = execute_in(
+            &dir,
+            &["--root", root.to_str().unwrap(), "apply", &manifest],
+        );
+
+        assert!(
+            matches!(&result, Err(Failure::Usage(m)) if m == "shop.service is in the manifest more than once"),
+            "{result:?}"
+        );
+        assert!(!root.exists());
+    }
+
+    #[test]
+    fn install_under_root_only_touches_the_staged_tree() {
+        let dir = test_dir("install-root");
+        let root = dir.join("root");
+        let args = [
+            "--root",
+            root.to_str().unwrap(),
+            "install",
+            "web.service",
+            "--name",
+            "css-staged",
+            "--set",
+            "PORT=80",
+            "--scope",
+            "system",
+            "--enable",
+        ];
+
+        execute_in(&dir, &args).unwrap();
+
+        let units = root.join("etc/systemd/system");
+        assert!(units.join("css-staged.service").exists());
+        assert!(units
+            .join("multi-user.target.wants/css-staged.service")
+            .is_symlink());
+        assert!(!Path::new("/etc/systemd/system/css-staged.service").exists());
+        assert!(
+            !Path::new("/etc/systemd/system/multi-user.target.wants/css-staged.service").exists()
+        );
+    }
+
+    #[test]
+    fn starting_under_root_is_refused_before_writing() {
+        let dir = test_dir("start-root");
+        let root = dir.join("root");
+        let root_arg = root.to_str().unwrap();
+
+        let install = execute_in(
+            &dir,
+            &[
+                "--root",
+                root_arg,
+                "install",
+                "web.service",
+                "--name",
+                "shop",
+                "--set",
+                "PORT=80",
+                "--scope",
+                "system",
+                "--start",
+            ],
+        );
+        assert!(
+            matches!(&install, Err(Failure::Usage(m)) if m.starts_with("--start is not available with --root")),
+            "{install:?}"
+        );
+
+        let manifest = manifest(
+            &dir,
+            "scope: system\nunits:\n\
+             - { template: web.service, name: api, variables: { PORT: '8080' } }\n\
+             - { template: web.service, name: shop, variables: { PORT: '80' }, start: true }\n",
+        );
+        let apply = execute_in(&dir, &["--root", root_arg, "apply", &manifest]);
+        assert!(
+            matches!(&apply, Err(Failure::Usage(m)) if m.ends_with("unit 2: start is not available with --root, nothing runs in the staged tree")),
+            "{apply:?}"
+        );
+        assert!(!root.exists());
+    }
+
+    #[test]
+    fn list_templates_names_each_template() {
+        let dir = test_dir("list-templates");
+        let output = execute_in(&dir, &["list-templates"]).unwrap();
+        assert!(output.lines().any(|name| name == "web.service"), "{output}");
+        let listed = parsed(execute_in(&dir, &["--json", "list-templates"]));
+        assert!(listed
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "name": "web.service", "type": "service" })));
+    }
+}
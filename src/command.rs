@@ -1,15 +1,18 @@
 use std::collections::HashMap;
 
-use crate::app::AppState;
+use crate::{app::AppState, config::KeyBindings};
 
 pub struct MenuCommand {
     pub name: &'static str,
-    pub shortcut: &'static str,
+    pub shortcut: String,
 }
 
 impl MenuCommand {
-    pub fn new(name: &'static str, shortcut: &'static str) -> Self {
-        Self { name, shortcut }
+    pub fn new(name: &'static str, shortcut: &str) -> Self {
+        Self {
+            name,
+            shortcut: shortcut.to_string(),
+        }
     }
 }
 
@@ -17,8 +20,11 @@ pub struct MenuCommands {
     pub commands: HashMap<AppState, Vec<MenuCommand>>,
 }
 
-impl Default for MenuCommands {
-    fn default() -> Self {
+impl MenuCommands {
+    /// The menus, showing the keys configured in `keys`.
+    pub fn new(keys: &KeyBindings) -> Self {
+        let exit = keys.exit.to_string();
+        let exit = exit.as_str();
         let commands = HashMap::from([
             (
                 AppState::SelectServiceTemplate,
@@ -26,7 +32,9 @@ impl Default for MenuCommands {
                     MenuCommand::new("Move Up", "↑"),
                     MenuCommand::new("Move Down", "🠗"),
                     MenuCommand::new("Select", "Enter"),
-                    MenuCommand::new("Exit", "^X"),
+                    MenuCommand::new("Import", &keys.import.to_string()),
+                    MenuCommand::new("Managed", &keys.managed.to_string()),
+                    MenuCommand::new("Exit", exit),
                 ],
             ),
             (
@@ -34,7 +42,7 @@ impl Default for MenuCommands {
                 vec![
                     MenuCommand::new("Select", "Enter"),
                     MenuCommand::new("Back", "ESC"),
-                    MenuCommand::new("Exit", "^X"),
+                    MenuCommand::new("Exit", exit),
                 ],
             ),
             (
@@ -42,7 +50,7 @@ impl Default for MenuCommands {
                 vec![
                     MenuCommand::new("Confirm", "Enter"),
                     MenuCommand::new("Back", "ESC"),
-                    MenuCommand::new("Exit", "^X"),
+                    MenuCommand::new("Exit", exit),
                 ],
             ),
             (
@@ -51,8 +59,11 @@ impl Default for MenuCommands {
                     MenuCommand::new("Move Up", "↑"),
                     MenuCommand::new("Move Down", "↑"),
                     MenuCommand::new("Edit", "Enter"),
-                    MenuCommand::new("Save", "^S"),
-                    MenuCommand::new("Exit", "^X"),
+                    MenuCommand::new("Save", &keys.save.to_string()),
+                    MenuCommand::new("Placeholder", &keys.placeholder.to_string()),
+                    MenuCommand::new("Save Template", &keys.save_template.to_string()),
+                    MenuCommand::new("Editor", &keys.edit.to_string()),
+                    MenuCommand::new("Exit", exit),
                 ],
             ),
             (
@@ -60,7 +71,115 @@ impl Default for MenuCommands {
                 vec![
                     MenuCommand::new("Confirm", "Enter"),
                     MenuCommand::new("Back", "ESC"),
-                    MenuCommand::new("Exit", "^X"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::ChooseTemplateName,
+                vec![
+                    MenuCommand::new("Save", "Enter"),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::ImportUnit,
+                vec![
+                    MenuCommand::new("Move Up", "↑"),
+                    MenuCommand::new("Move Down", "🠗"),
+                    MenuCommand::new("New Service", "Enter"),
+                    MenuCommand::new("Import Template", &keys.import_template.to_string()),
+                    MenuCommand::new("Override", &keys.override_unit.to_string()),
+                    MenuCommand::new("Edit", &keys.edit_unit.to_string()),
+                    MenuCommand::new("Remove", &keys.remove_unit.to_string()),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::SelectMixins,
+                vec![
+                    MenuCommand::new("Move Up", "↑"),
+                    MenuCommand::new("Move Down", "🠗"),
+                    MenuCommand::new("Toggle", &keys.toggle_mixin.to_string()),
+                    MenuCommand::new("Continue", "Enter"),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::ChooseScope,
+                vec![
+                    MenuCommand::new("Move Up", "↑"),
+                    MenuCommand::new("Move Down", "🠗"),
+                    MenuCommand::new("Save", "Enter"),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::ConfirmOverwrite,
+                vec![
+                    MenuCommand::new("Overwrite", &keys.overwrite.to_string()),
+                    MenuCommand::new("New Name", &keys.rename.to_string()),
+                    MenuCommand::new("Drop-in", &keys.dropin.to_string()),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::SaveOpenedUnit,
+                vec![
+                    MenuCommand::new("In Place", &keys.in_place.to_string()),
+                    MenuCommand::new("Drop-in", &keys.dropin.to_string()),
+                    MenuCommand::new("Copy To Scope", &keys.copy_to_scope.to_string()),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::RenameUnit,
+                vec![
+                    MenuCommand::new("Save", "Enter"),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::PostSave,
+                vec![
+                    MenuCommand::new("Move Up", "↑"),
+                    MenuCommand::new("Move Down", "🠗"),
+                    MenuCommand::new("Run", "Enter"),
+                    MenuCommand::new("Status", &keys.status.to_string()),
+                    MenuCommand::new("Done", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::ConfirmRemoval,
+                vec![
+                    MenuCommand::new("Remove", "Enter"),
+                    MenuCommand::new("Cancel", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::ManagedUnits,
+                vec![
+                    MenuCommand::new("Move Up", "↑"),
+                    MenuCommand::new("Move Down", "🠗"),
+                    MenuCommand::new("Open", "Enter"),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
+                ],
+            ),
+            (
+                AppState::UnitStatus,
+                vec![
+                    MenuCommand::new("Refresh", "Enter"),
+                    MenuCommand::new("Back", "ESC"),
+                    MenuCommand::new("Exit", exit),
                 ],
             ),
         ]);
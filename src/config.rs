@@ -0,0 +1,422 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+use crate::{
+    app::App,
+    lint::{self, LintLevel},
+    scope::Scope,
+    systemctl::PostSaveAction,
+};
+
+/// Settings read from config.toml in the config directory, e.g.
+///
+/// ```toml
+/// default_scope = "user"
+/// default_template = "web.service"
+/// editor = "nvim"
+/// theme = "/home/me/.config/create-systemd-service/solarized.tmTheme"
+/// list_width = 40
+/// post_save_actions = ["daemon-reload", "enable-now", "restart"]
+/// verify = "block"
+///
+/// [keys]
+/// save = "ctrl-w"
+/// exit = ["ctrl-x", "ctrl-q"]
+///
+/// [lint]
+/// user-root = "off"
+/// exec-not-absolute = "error"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Scope units are saved in; the system scope for root and the user
+    /// scope for everyone else if unset.
+    pub default_scope: Option<Scope>,
+    /// Template selected when the TUI starts.
+    pub default_template: Option<String>,
+    /// Command the editor key opens the unit with, $VISUAL or $EDITOR if unset.
+    pub editor: Option<String>,
+    /// A .tmTheme file for the syntax highlighting instead of the built-in monokai.
+    pub theme: Option<PathBuf>,
+    /// Escalation command, see --escalate.
+    pub escalate: Option<String>,
+    /// Width of the list on the left of the two-pane screens, in percent.
+    pub list_width: u16,
+    /// Actions offered after saving, in this order.
+    pub post_save_actions: Vec<PostSaveAction>,
+    pub verify: Verification,
+    pub keys: KeyBindings,
+    /// Levels for lint rules by id, see list-lint-rules.
+    pub lint: BTreeMap<String, LintLevel>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            default_scope: None,
+            default_template: None,
+            editor: None,
+            theme: None,
+            escalate: None,
+            list_width: 30,
+            post_save_actions: PostSaveAction::ALL.to_vec(),
+            verify: Verification::default(),
+            keys: KeyBindings::default(),
+            lint: BTreeMap::new(),
+        }
+    }
+}
+
+/// What to do with systemd-analyze verify's findings before saving.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verification {
+    /// Do not run systemd-analyze.
+    Off,
+    /// Show the findings but save anyway.
+    #[default]
+    Warn,
+    /// Refuse to save units with errors, like --block-on-verify-errors.
+    Block,
+}
+
+impl Config {
+    /// ~/.config/create-systemd-service/config.toml
+    pub fn default_path() -> PathBuf {
+        App::templates_dir().join("config.toml")
+    }
+
+    /// Reads the config at `path`; a missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(format!("unable to read {}: {e}", path.display())),
+        };
+        let config: Config =
+            toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+
+        if !(10..=90).contains(&config.list_width) {
+            return Err(format!(
+                "{}: list_width must be between 10 and 90, not {}",
+                path.display(),
+                config.list_width
+            ));
+        }
+        if config.post_save_actions.is_empty() {
+            return Err(format!(
+                "{}: post_save_actions needs at least one action",
+                path.display()
+            ));
+        }
+        if let Some(key) = config.keys.exit.0.iter().find(|key| !key.ctrl) {
+            return Err(format!(
+                "{}: keys.exit must be a control key like \"ctrl-x\" as it works while typing, not {:?}",
+                path.display(),
+                key.to_string()
+            ));
+        }
+        if let Some((first, second, key)) = config.keys.collision() {
+            return Err(format!(
+                "{}: keys.{first} and keys.{second} are both bound to {:?}",
+                path.display(),
+                key.to_string()
+            ));
+        }
+        let rules = lint::rule_ids();
+        if let Some(unknown) = config.lint.keys().find(|id| !rules.contains(&id.as_str())) {
+            return Err(format!(
+                "{}: unknown lint rule {unknown:?}, expected one of {}",
+                path.display(),
+                rules.join(", ")
+            ));
+        }
+        Ok(config)
+    }
+}
+
+/// A key, written as "q", "ctrl-s" or "space" in the config and shown as
+/// "q", "^S" or "Space".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub ch: char,
+    pub ctrl: bool,
+}
+
+impl Key {
+    const fn plain(ch: char) -> Self {
+        Key { ch, ctrl: false }
+    }
+
+    const fn ctrl(ch: char) -> Self {
+        Key { ch, ctrl: true }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let (ctrl, rest) = match text
+            .strip_prefix("ctrl-")
+            .or_else(|| text.strip_prefix("ctrl+"))
+            .or_else(|| text.strip_prefix('^'))
+        {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        if rest == "space" {
+            return Ok(Key { ch: ' ', ctrl });
+        }
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) if !ch.is_whitespace() => Ok(Key {
+                ch: if ctrl { ch.to_ascii_lowercase() } else { ch },
+                ctrl,
+            }),
+            _ => Err(format!(
+                "invalid key {text:?}, expected a single character like \"q\", \"ctrl-s\" or \"space\""
+            )),
+        }
+    }
+
+    fn matches(&self, key: &KeyEvent) -> bool {
+        let ctrl = key.modifiers == KeyModifiers::CONTROL;
+        ctrl == self.ctrl && key.code == KeyCode::Char(self.ch)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ch == ' ' {
+            write!(f, "{}Space", if self.ctrl { "^" } else { "" })
+        } else if self.ctrl {
+            write!(f, "^{}", self.ch.to_ascii_uppercase())
+        } else {
+            write!(f, "{}", self.ch)
+        }
+    }
+}
+
+/// One key or several keys for the same command.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "KeyList")]
+pub struct Binding(Vec<Key>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl TryFrom<KeyList> for Binding {
+    type Error = String;
+
+    fn try_from(list: KeyList) -> Result<Self, Self::Error> {
+        let keys = match list {
+            KeyList::One(key) => vec![key],
+            KeyList::Many(keys) => keys,
+        };
+        if keys.is_empty() {
+            return Err("a binding needs at least one key".to_string());
+        }
+        keys.iter()
+            .map(|key| Key::parse(key))
+            .collect::<Result<_, _>>()
+            .map(Binding)
+    }
+}
+
+impl Binding {
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        self.0.iter().any(|k| k.matches(key))
+    }
+
+    fn shared_key(&self, other: &Binding) -> Option<Key> {
+        self.0.iter().copied().find(|key| other.0.contains(key))
+    }
+}
+
+/// Shows the first key, the one the menu bar advertises.
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0[0])
+    }
+}
+
+/// Keys that can be changed in the `[keys]` table. `exit` is checked on every
+/// screen, including the text inputs, so it has to be a control key.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    /// Leave the program from any screen.
+    pub exit: Binding,
+    /// Leave the program from the template list.
+    pub quit: Binding,
+    pub save: Binding,
+    pub save_template: Binding,
+    pub placeholder: Binding,
+    /// Open the unit in the external editor.
+    pub edit: Binding,
+    pub import: Binding,
+    pub managed: Binding,
+    pub import_template: Binding,
+    pub override_unit: Binding,
+    pub edit_unit: Binding,
+    pub remove_unit: Binding,
+    pub toggle_mixin: Binding,
+    /// Offered when the unit to save already exists.
+    pub overwrite: Binding,
+    pub rename: Binding,
+    pub dropin: Binding,
+    /// Offered when saving a unit opened from disk.
+    pub in_place: Binding,
+    pub copy_to_scope: Binding,
+    /// Show the status of the saved unit.
+    pub status: Binding,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            exit: Binding(vec![Key::ctrl('x'), Key::ctrl('v')]),
+            quit: Binding(vec![Key::plain('q')]),
+            save: Binding(vec![Key::ctrl('s')]),
+            save_template: Binding(vec![Key::ctrl('t')]),
+            placeholder: Binding(vec![Key::ctrl('p')]),
+            edit: Binding(vec![Key::ctrl('e')]),
+            import: Binding(vec![Key::plain('i')]),
+            managed: Binding(vec![Key::plain('m')]),
+            import_template: Binding(vec![Key::plain('t')]),
+            override_unit: Binding(vec![Key::plain('o')]),
+            edit_unit: Binding(vec![Key::plain('e')]),
+            remove_unit: Binding(vec![Key::plain('r')]),
+            toggle_mixin: Binding(vec![Key::plain(' ')]),
+            overwrite: Binding(vec![Key::plain('o')]),
+            rename: Binding(vec![Key::plain('n')]),
+            dropin: Binding(vec![Key::plain('d')]),
+            in_place: Binding(vec![Key::plain('p')]),
+            copy_to_scope: Binding(vec![Key::plain('c')]),
+            status: Binding(vec![Key::plain('v')]),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// The bindings checked together on one screen, by their name in the
+    /// `[keys]` table. `exit` is on all of them.
+    fn screens(&self) -> [Vec<(&'static str, &Binding)>; 7] {
+        [
+            vec![
+                ("quit", &self.quit),
+                ("import", &self.import),
+                ("managed", &self.managed),
+            ],
+            vec![
+                ("save", &self.save),
+                ("save_template", &self.save_template),
+                ("placeholder", &self.placeholder),
+                ("edit", &self.edit),
+            ],
+            vec![
+                ("import_template", &self.import_template),
+                ("override_unit", &self.override_unit),
+                ("edit_unit", &self.edit_unit),
+                ("remove_unit", &self.remove_unit),
+            ],
+            vec![("toggle_mixin", &self.toggle_mixin)],
+            vec![
+                ("overwrite", &self.overwrite),
+                ("rename", &self.rename),
+                ("dropin", &self.dropin),
+            ],
+            vec![
+                ("in_place", &self.in_place),
+                ("dropin", &self.dropin),
+                ("copy_to_scope", &self.copy_to_scope),
+            ],
+            vec![("status", &self.status)],
+        ]
+    }
+
+    /// Two bindings of the same screen sharing a key, where only the first
+    /// would ever run.
+    fn collision(&self) -> Option<(&'static str, &'static str, Key)> {
+        self.screens().into_iter().find_map(|screen| {
+            let bindings: Vec<_> = [("exit", &self.exit)].into_iter().chain(screen).collect();
+            bindings
+                .iter()
+                .enumerate()
+                .find_map(|(i, (name, binding))| {
+                    bindings[i + 1..].iter().find_map(|(other, other_binding)| {
+                        binding
+                            .shared_key(other_binding)
+                            .map(|key| (*name, *other, key))
+                    })
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn load(name: &str, text: &str) -> Result<Config, String> {
+        let path = std::env::temp_dir().join(format!("css-config-{}-{name}.toml", process::id()));
+        fs::write(&path, text).unwrap();
+        let config = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn exit_must_be_a_control_key() {
+        let e = load("plain-exit", "[keys]\nexit = \"x\"\n").unwrap_err();
+        assert!(e.contains("must be a control key"), "{e}");
+        let e = load("mixed-exit", "[keys]\nexit = [\"ctrl-x\", \"q\"]\n").unwrap_err();
+        assert!(e.contains("not \"q\""), "{e}");
+        assert!(load("ctrl-exit", "[keys]\nexit = \"^q\"\n").is_ok());
+    }
+
+    #[test]
+    fn bindings_on_one_screen_must_not_collide() {
+        let e = load("exit-collision", "[keys]\nsave = \"ctrl-x\"\n").unwrap_err();
+        assert!(
+            e.contains("keys.exit and keys.save are both bound to \"^X\""),
+            "{e}"
+        );
+        let e = load("import-collision", "[keys]\nremove_unit = [\"x\", \"t\"]\n").unwrap_err();
+        assert!(
+            e.contains("keys.import_template and keys.remove_unit are both bound to \"t\""),
+            "{e}"
+        );
+        let e = load("save-collision", "[keys]\nin_place = \"c\"\n").unwrap_err();
+        assert!(
+            e.contains("keys.in_place and keys.copy_to_scope are both bound to \"c\""),
+            "{e}"
+        );
+        // Keys of different screens may be the same, as 'o' is by default.
+        assert!(load("other-screen", "[keys]\nstatus = \"t\"\n").is_ok());
+    }
+
+    #[test]
+    fn space_is_written_out() {
+        let config = load("space", "[keys]\ntoggle_mixin = [\"space\", \"x\"]\n").unwrap();
+        assert_eq!(config.keys.toggle_mixin.to_string(), "Space");
+        assert!(load("blank", "[keys]\ntoggle_mixin = \" \"\n").is_err());
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        let config = Config::load(Path::new("/nonexistent/config.toml")).unwrap();
+        assert_eq!(config.list_width, 30);
+        assert_eq!(config.keys.exit.to_string(), "^X");
+    }
+}
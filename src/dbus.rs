@@ -0,0 +1,280 @@
+use std::{io, sync::Arc};
+
+use zbus::{
+    blocking::Connection,
+    zvariant::{DynamicType, OwnedObjectPath, OwnedValue},
+    Message,
+};
+
+use crate::{
+    scope::Scope,
+    systemctl::{UnitManager, UnitState},
+};
+
+const DESTINATION: &str = "org.freedesktop.systemd1";
+const MANAGER_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+const UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// `UnitManager` that talks to org.freedesktop.systemd1 directly instead of
+/// running systemctl.
+///
+/// System scope uses the system bus and user scope the session bus. Connections
+/// are opened on first use from DBUS_SYSTEM_BUS_ADDRESS and
+/// DBUS_SESSION_BUS_ADDRESS, so pointing those at a private bus lets a stand-in
+/// service take the place of systemd.
+#[derive(Default)]
+pub struct DbusManager {
+    system: Option<Connection>,
+    session: Option<Connection>,
+}
+
+fn to_io(e: zbus::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+impl DbusManager {
+    fn connection(&mut self, scope: Scope) -> io::Result<&Connection> {
+        let connection = match scope {
+            Scope::System => &mut self.system,
+            Scope::User => &mut self.session,
+            Scope::GlobalUser => {
+                return Err(io::Error::other(
+                    "the global user scope has no bus, log in as a user instead",
+                ))
+            }
+        };
+
+        if connection.is_none() {
+            let connected = match scope {
+                Scope::System => Connection::system(),
+                _ => Connection::session(),
+            };
+            *connection = Some(connected.map_err(to_io)?);
+        }
+        Ok(connection.as_ref().expect("connected above"))
+    }
+
+    fn call_manager<B>(&mut self, scope: Scope, method: &str, body: &B) -> io::Result<Arc<Message>>
+    where
+        B: serde::Serialize + DynamicType,
+    {
+        self.connection(scope)?
+            .call_method(
+                Some(DESTINATION),
+                MANAGER_PATH,
+                Some(MANAGER_INTERFACE),
+                method,
+                body,
+            )
+            .map_err(to_io)
+    }
+
+    fn unit_property(
+        &mut self,
+        scope: Scope,
+        unit_path: &OwnedObjectPath,
+        property: &str,
+    ) -> io::Result<String> {
+        let reply = self
+            .connection(scope)?
+            .call_method(
+                Some(DESTINATION),
+                unit_path.as_str(),
+                Some(PROPERTIES_INTERFACE),
+                "Get",
+                &(UNIT_INTERFACE, property),
+            )
+            .map_err(to_io)?;
+        let value: OwnedValue = reply.body().map_err(to_io)?;
+        String::try_from(value).map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+impl UnitManager for DbusManager {
+    fn daemon_reload(&mut self, scope: Scope) -> io::Result<()> {
+        self.call_manager(scope, "Reload", &())?;
+        Ok(())
+    }
+
+    fn enable(&mut self, scope: Scope, unit: &str, now: bool) -> io::Result<()> {
+        // Arguments are the unit files, runtime-only and force.
+        self.call_manager(scope, "EnableUnitFiles", &(vec![unit], false, false))?;
+        // systemctl enable reloads so the new symlinks are picked up.
+        self.daemon_reload(scope)?;
+        if now {
+            self.start(scope, unit)?;
+        }
+        Ok(())
+    }
+
+    fn disable(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        // Arguments are the unit files and runtime-only.
+        self.call_manager(scope, "DisableUnitFiles", &(vec![unit], false))?;
+        self.daemon_reload(scope)?;
+        Ok(())
+    }
+
+    fn start(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.call_manager(scope, "StartUnit", &(unit, "replace"))?;
+        Ok(())
+    }
+
+    fn stop(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.call_manager(scope, "StopUnit", &(unit, "replace"))?;
+        Ok(())
+    }
+
+    fn restart(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.call_manager(scope, "RestartUnit", &(unit, "replace"))?;
+        Ok(())
+    }
+
+    fn unit_state(&mut self, scope: Scope, unit: &str) -> io::Result<UnitState> {
+        // Unlike GetUnit, LoadUnit also answers for units that are not loaded.
+        let reply = self.call_manager(scope, "LoadUnit", &(unit,))?;
+        let unit_path: OwnedObjectPath = reply.body().map_err(to_io)?;
+
+        Ok(UnitState {
+            active_state: self.unit_property(scope, &unit_path, "ActiveState")?,
+            sub_state: self.unit_property(scope, &unit_path, "SubState")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::net::UnixStream,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use zbus::{blocking::ConnectionBuilder, dbus_interface, Guid};
+
+    use super::*;
+
+    const UNIT_PATH: &str = "/org/freedesktop/systemd1/unit/foo_2eservice";
+
+    /// Stand-in for systemd's manager object that records the calls it gets.
+    struct StandInManager {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StandInManager {
+        fn record(&self, call: String) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    #[dbus_interface(name = "org.freedesktop.systemd1.Manager")]
+    impl StandInManager {
+        fn reload(&self) {
+            self.record("Reload".to_string());
+        }
+
+        fn enable_unit_files(
+            &self,
+            files: Vec<String>,
+            runtime: bool,
+            force: bool,
+        ) -> (bool, Vec<(String, String, String)>) {
+            self.record(format!("EnableUnitFiles {files:?} {runtime} {force}"));
+            (true, vec![])
+        }
+
+        fn start_unit(&self, name: String, mode: String) -> OwnedObjectPath {
+            self.record(format!("StartUnit {name} {mode}"));
+            OwnedObjectPath::try_from("/org/freedesktop/systemd1/job/1").unwrap()
+        }
+
+        fn load_unit(&self, name: String) -> OwnedObjectPath {
+            self.record(format!("LoadUnit {name}"));
+            OwnedObjectPath::try_from(UNIT_PATH).unwrap()
+        }
+    }
+
+    struct StandInUnit;
+
+    #[dbus_interface(name = "org.freedesktop.systemd1.Unit")]
+    impl StandInUnit {
+        #[dbus_interface(property)]
+        fn active_state(&self) -> String {
+            "active".to_string()
+        }
+
+        #[dbus_interface(property)]
+        fn sub_state(&self) -> String {
+            "running".to_string()
+        }
+    }
+
+    /// A `DbusManager` whose system scope talks to a stand-in service over a
+    /// private connection, and the calls the stand-in received.
+    fn stand_in() -> (DbusManager, Arc<Mutex<Vec<String>>>, Connection) {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let (server_end, client_end) = UnixStream::pair().unwrap();
+        let manager = StandInManager {
+            calls: calls.clone(),
+        };
+        let server = thread::spawn(move || {
+            let guid = Guid::generate();
+            ConnectionBuilder::unix_stream(server_end)
+                .server(&guid)
+                .p2p()
+                .serve_at(MANAGER_PATH, manager)
+                .unwrap()
+                .serve_at(UNIT_PATH, StandInUnit)
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = ConnectionBuilder::unix_stream(client_end)
+            .p2p()
+            .build()
+            .unwrap();
+        let server = server.join().unwrap();
+
+        let manager = DbusManager {
+            system: Some(client),
+            session: None,
+        };
+        (manager, calls, server)
+    }
+
+    #[test]
+    fn enable_now_enables_reloads_and_starts() {
+        let (mut manager, calls, _server) = stand_in();
+
+        manager.enable(Scope::System, "foo.service", true).unwrap();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                r#"EnableUnitFiles ["foo.service"] false false"#,
+                "Reload",
+                "StartUnit foo.service replace",
+            ]
+        );
+    }
+
+    #[test]
+    fn unit_state_reads_the_unit_properties() {
+        let (mut manager, calls, _server) = stand_in();
+
+        let state = manager.unit_state(Scope::System, "foo.service").unwrap();
+
+        assert_eq!(state.active_state, "active");
+        assert_eq!(state.sub_state, "running");
+        assert_eq!(*calls.lock().unwrap(), ["LoadUnit foo.service"]);
+    }
+
+    #[test]
+    fn global_user_scope_has_no_bus() {
+        let (mut manager, calls, _server) = stand_in();
+
+        assert!(manager.daemon_reload(Scope::GlobalUser).is_err());
+        assert!(calls.lock().unwrap().is_empty());
+    }
+}
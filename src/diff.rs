@@ -0,0 +1,43 @@
+use similar::{ChangeTag, TextDiff};
+
+/// One row of a unified diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    /// `@@ -a,b +c,d @@` header starting a hunk.
+    Hunk(String),
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+impl DiffLine {
+    /// The line without its diff marker.
+    pub fn text(&self) -> &str {
+        match self {
+            DiffLine::Hunk(text)
+            | DiffLine::Context(text)
+            | DiffLine::Removed(text)
+            | DiffLine::Added(text) => text,
+        }
+    }
+}
+
+/// Line based unified diff from `old` to `new` with three lines of context.
+pub fn unified_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = vec![];
+
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        lines.push(DiffLine::Hunk(hunk.header().to_string()));
+        for change in hunk.iter_changes() {
+            let text = change.value().trim_end_matches('\n').to_string();
+            lines.push(match change.tag() {
+                ChangeTag::Equal => DiffLine::Context(text),
+                ChangeTag::Delete => DiffLine::Removed(text),
+                ChangeTag::Insert => DiffLine::Added(text),
+            });
+        }
+    }
+
+    lines
+}
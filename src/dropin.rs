@@ -0,0 +1,153 @@
+use crate::unitfile::{is_list_directive, Section, UnitFile};
+
+/// Name of the drop-in written next to a unit, as `systemctl edit` does.
+pub const OVERRIDE_FILE_NAME: &str = "override.conf";
+
+/// List directives only some unit types take more than once: a service that
+/// is not Type=oneshot refuses a second ExecStart=, so these are never extended.
+const ALWAYS_RESET: &[&str] = &["ExecStart"];
+
+/// Directives a drop-in needs so that `original` behaves like `edited`.
+///
+/// Unchanged directives are left out. A changed or removed single-valued
+/// directive is assigned its new value, or reset with `Key=`. A list directive
+/// whose new values merely extend the old ones only gets the extra values,
+/// unless it is ExecStart=; any other change resets the list with `Key=` and
+/// assigns it again.
+pub fn compute_dropin(original: &UnitFile, edited: &UnitFile) -> UnitFile {
+    let mut dropin = UnitFile::default();
+    let empty = Section::new("");
+
+    let mut section_names: Vec<&str> = edited.sections.iter().map(|s| s.name.as_str()).collect();
+    for section in &original.sections {
+        if !section_names.contains(&section.name.as_str()) {
+            section_names.push(&section.name);
+        }
+    }
+
+    for name in section_names {
+        let old = original
+            .sections
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap_or(&empty);
+        let new = edited
+            .sections
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap_or(&empty);
+
+        let mut keys: Vec<&str> = vec![];
+        for directive in new.directives().chain(old.directives()) {
+            if !keys.contains(&directive.key.as_str()) {
+                keys.push(&directive.key);
+            }
+        }
+
+        let mut changes = Section::new(name);
+        for key in keys {
+            let old_values = effective_values(old, key);
+            let new_values = effective_values(new, key);
+
+            if is_list_directive(key) {
+                if old_values == new_values {
+                    continue;
+                }
+                if new_values.starts_with(&old_values) && !ALWAYS_RESET.contains(&key) {
+                    for value in &new_values[old_values.len()..] {
+                        changes.push(key, value);
+                    }
+                } else {
+                    changes.push(key, "");
+                    for value in &new_values {
+                        changes.push(key, value);
+                    }
+                }
+            } else {
+                let old_value = old_values.last().copied().unwrap_or("");
+                let new_value = new_values.last().copied().unwrap_or("");
+                if old_value != new_value {
+                    changes.push(key, new_value);
+                }
+            }
+        }
+
+        if changes.directives().next().is_some() {
+            dropin.section_or_insert(name).lines = changes.lines;
+        }
+    }
+
+    dropin
+}
+
+/// Values of `key` that are still in effect, i.e. those after the last empty assignment.
+fn effective_values<'a>(section: &'a Section, key: &str) -> Vec<&'a str> {
+    let values = section.values(key);
+    let start = values
+        .iter()
+        .rposition(|v| v.is_empty())
+        .map_or(0, |i| i + 1);
+    values[start..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "[Unit]\nDescription=Web\nAfter=network.target\n\n\
+                            [Service]\nExecStart=/usr/bin/web\nUser=web\nRestart=always\n";
+
+    fn dropin(edited: &str) -> String {
+        let original = UnitFile::parse(ORIGINAL).unwrap();
+        compute_dropin(&original, &UnitFile::parse(edited).unwrap()).to_string()
+    }
+
+    #[test]
+    fn unchanged_units_need_no_dropin() {
+        assert_eq!(dropin(ORIGINAL), "");
+    }
+
+    #[test]
+    fn changed_values_are_assigned_again() {
+        let edited = ORIGINAL
+            .replace("After=network.target", "After=local-fs.target")
+            .replace("User=web", "User=www");
+
+        assert_eq!(
+            dropin(&edited),
+            "[Unit]\nAfter=\nAfter=local-fs.target\n\n[Service]\nUser=www\n"
+        );
+    }
+
+    #[test]
+    fn lists_are_extended() {
+        let edited = ORIGINAL.replace(
+            "After=network.target",
+            "After=network.target\nAfter=local-fs.target",
+        );
+
+        assert_eq!(dropin(&edited), "[Unit]\nAfter=local-fs.target\n");
+    }
+
+    #[test]
+    fn exec_start_is_always_reset() {
+        let edited = ORIGINAL.replace(
+            "ExecStart=/usr/bin/web",
+            "ExecStart=/usr/bin/web\nExecStart=/usr/bin/web --again",
+        );
+
+        assert_eq!(
+            dropin(&edited),
+            "[Service]\nExecStart=\nExecStart=/usr/bin/web\nExecStart=/usr/bin/web --again\n"
+        );
+    }
+
+    #[test]
+    fn removed_directives_are_reset() {
+        let edited = ORIGINAL
+            .replace("After=network.target\n", "")
+            .replace("Restart=always\n", "");
+
+        assert_eq!(dropin(&edited), "[Unit]\nAfter=\n\n[Service]\nRestart=\n");
+    }
+}
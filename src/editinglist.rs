@@ -44,7 +44,7 @@ impl EditingList {
     pub fn previous(&mut self) {
         let i = self.state.selected().unwrap();
 
-        if i <= 0 {
+        if i == 0 {
             return;
         }
 
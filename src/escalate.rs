@@ -0,0 +1,207 @@
+use std::{
+    env,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossterm::{
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use nix::unistd::{access, AccessFlags, Uid};
+
+/// Escalation commands tried in order when none is given with --escalate.
+const KNOWN_COMMANDS: [&str; 3] = ["run0", "sudo", "pkexec"];
+
+/// Set while the terminal was handed to an escalation command, which may have
+/// drawn a password prompt over the TUI.
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// Runs the few steps that need root when the app itself is unprivileged.
+///
+/// Only writing unit files and their links and calling systemctl go through
+/// this, so the backend can be swapped for one that records the calls in tests.
+pub trait Escalator {
+    /// Runs `program` with `args` as root, feeding it `input` on standard input,
+    /// and returns its standard output.
+    fn run(&mut self, program: &str, args: &[&str], input: Option<&str>) -> io::Result<String>;
+
+    /// Whether changing `path` has to go through this escalator.
+    fn needed_for(&self, path: &Path) -> bool {
+        needs_root(path)
+    }
+}
+
+/// `Escalator` that prefixes the command with sudo, pkexec, run0 or anything
+/// else that takes a command line to run as root.
+pub struct CommandEscalator {
+    pub prefix: Vec<String>,
+    /// Leave the TUI's alternate screen and raw mode while the command runs.
+    /// Only the TUI sets this; on the command line the terminal is left alone.
+    pub suspend_terminal: bool,
+}
+
+impl CommandEscalator {
+    /// Parses a command such as "sudo" or "sudo -A" given with --escalate.
+    pub fn parse(command: &str) -> Option<Self> {
+        let prefix: Vec<_> = command.split_whitespace().map(str::to_string).collect();
+        (!prefix.is_empty()).then_some(CommandEscalator {
+            prefix,
+            suspend_terminal: false,
+        })
+    }
+
+    /// The first of run0, sudo and pkexec found in $PATH.
+    pub fn detect() -> Option<Self> {
+        let path = env::var_os("PATH")?;
+        KNOWN_COMMANDS.iter().find_map(|command| {
+            env::split_paths(&path)
+                .any(|dir| dir.join(command).is_file())
+                .then(|| CommandEscalator {
+                    prefix: vec![command.to_string()],
+                    suspend_terminal: false,
+                })
+        })
+    }
+}
+
+impl Escalator for CommandEscalator {
+    fn run(&mut self, program: &str, args: &[&str], input: Option<&str>) -> io::Result<String> {
+        let mut command = Command::new(&self.prefix[0]);
+        command
+            .args(&self.prefix[1..])
+            .arg(program)
+            .args(args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut run = || {
+            command.spawn().and_then(|mut child| {
+                if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+                    stdin.write_all(input.as_bytes())?;
+                }
+                child.wait_with_output()
+            })
+        };
+        // Give the terminal back for the duration, sudo may ask for a password.
+        let output = if self.suspend_terminal {
+            with_terminal_suspended(run)?
+        } else {
+            run()?
+        };
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        } else {
+            Err(io::Error::other(format!(
+                "{} {program} failed: {}",
+                self.prefix.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+}
+
+/// The escalation command to use, or `None` when running as root already.
+/// `command` is the one given with --escalate, otherwise a known one is looked up.
+/// The TUI passes `suspend_terminal` so that password prompts show up.
+pub fn escalator(command: Option<&str>, suspend_terminal: bool) -> Option<Box<dyn Escalator>> {
+    if Uid::effective().is_root() {
+        return None;
+    }
+    let escalator = match command {
+        Some(command) => CommandEscalator::parse(command),
+        None => CommandEscalator::detect(),
+    };
+    escalator.map(|e| {
+        Box::new(CommandEscalator {
+            suspend_terminal,
+            ..e
+        }) as Box<dyn Escalator>
+    })
+}
+
+/// Whether writing `path` needs root: its nearest existing directory is not
+/// writable by the current user.
+pub fn needs_root(path: &Path) -> bool {
+    path.ancestors()
+        .skip(1)
+        .find(|dir| dir.exists())
+        .is_some_and(|dir| access(dir, AccessFlags::W_OK).is_err())
+}
+
+/// Whether the terminal was suspended since the last call, in which case the
+/// screen has to be redrawn from scratch.
+pub fn take_suspended() -> bool {
+    SUSPENDED.swap(false, Ordering::Relaxed)
+}
+
+/// Runs `f` with the terminal out of raw mode and the alternate screen, for
+/// programs that need it, such as password prompts or an editor.
+pub fn with_terminal_suspended<T>(f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    suspend_terminal()?;
+    let result = f();
+    resume_terminal()?;
+    result
+}
+
+fn suspend_terminal() -> io::Result<()> {
+    SUSPENDED.store(true, Ordering::Relaxed);
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)
+}
+
+fn resume_terminal() -> io::Result<()> {
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    enable_raw_mode()
+}
+
+/// `Escalator` that records the commands it is asked to run instead of running
+/// them, and claims everything below `root_only` needs it.
+#[cfg(test)]
+pub struct RecordingEscalator {
+    pub root_only: std::path::PathBuf,
+    /// Program, arguments and standard input of each call.
+    pub calls: Vec<(String, Vec<String>, Option<String>)>,
+    /// Calls whose arguments mention this fail.
+    pub fail_on: Option<String>,
+}
+
+#[cfg(test)]
+impl RecordingEscalator {
+    pub fn new(root_only: &Path) -> Self {
+        RecordingEscalator {
+            root_only: root_only.to_path_buf(),
+            calls: vec![],
+            fail_on: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Escalator for RecordingEscalator {
+    fn run(&mut self, program: &str, args: &[&str], input: Option<&str>) -> io::Result<String> {
+        self.calls.push((
+            program.to_string(),
+            args.iter().map(|arg| arg.to_string()).collect(),
+            input.map(str::to_string),
+        ));
+        match &self.fail_on {
+            Some(fail_on) if args.iter().any(|arg| arg.contains(fail_on.as_str())) => {
+                Err(io::Error::other(format!("{program} failed")))
+            }
+            _ => Ok(String::new()),
+        }
+    }
+
+    fn needed_for(&self, path: &Path) -> bool {
+        path.starts_with(&self.root_only)
+    }
+}
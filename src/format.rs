@@ -0,0 +1,185 @@
+use crate::{
+    unitfile::{Line, Section, UnitFile},
+    unittype::UnitType,
+};
+
+/// Position of a section in a formatted unit: [Unit] first, then the unit
+/// type's own section, then anything else in its original order, [Install] last.
+fn section_rank(name: &str) -> usize {
+    if name == "Unit" {
+        0
+    } else if UnitType::ALL.iter().any(|t| t.type_section() == Some(name)) {
+        1
+    } else if name == "Install" {
+        3
+    } else {
+        2
+    }
+}
+
+/// Drops blank lines at either end and collapses runs of them into one.
+fn tidy_blanks(lines: Vec<Line>) -> Vec<Line> {
+    let mut tidy: Vec<Line> = vec![];
+    for line in lines {
+        let line = match line {
+            Line::Comment(comment) => Line::Comment(comment.trim_end().to_string()),
+            line => line,
+        };
+        if line == Line::Blank && matches!(tidy.last(), None | Some(Line::Blank)) {
+            continue;
+        }
+        tidy.push(line);
+    }
+    if tidy.last() == Some(&Line::Blank) {
+        tidy.pop();
+    }
+    tidy
+}
+
+/// Sorts directives by key. Comments move with the directive below them and
+/// repeated keys keep their order, which matters for lists like ExecStart=.
+fn sort_directives(lines: Vec<Line>) -> Vec<Line> {
+    let mut groups: Vec<(String, Vec<Line>)> = vec![];
+    let mut pending = vec![];
+    for line in lines {
+        match line {
+            Line::Blank => (),
+            Line::Comment(_) => pending.push(line),
+            Line::Directive(ref directive) => {
+                let key = directive.key.clone();
+                pending.push(line);
+                groups.push((key, std::mem::take(&mut pending)));
+            }
+        }
+    }
+    groups.sort_by(|a, b| a.0.cmp(&b.0));
+    groups
+        .into_iter()
+        .flat_map(|(_, lines)| lines)
+        .chain(pending)
+        .collect()
+}
+
+/// `unit` laid out consistently: sections in their usual order with repeated
+/// headers merged, one blank line between sections and none doubled, and the
+/// directives sorted by key if `sort` is set.
+pub fn normalize(unit: &UnitFile, sort: bool) -> UnitFile {
+    let mut sections: Vec<Section> = vec![];
+    for section in &unit.sections {
+        match sections.iter_mut().find(|s| s.name == section.name) {
+            Some(merged) => {
+                // The blank line that ended the earlier header's part is not
+                // one the merged section needs.
+                while merged.lines.last() == Some(&Line::Blank) {
+                    merged.lines.pop();
+                }
+                merged.lines.extend(section.lines.iter().cloned());
+            }
+            None => sections.push(section.clone()),
+        }
+    }
+    sections.sort_by_key(|section| section_rank(&section.name));
+
+    let count = sections.len();
+    for (i, section) in sections.iter_mut().enumerate() {
+        let lines = std::mem::take(&mut section.lines);
+        let lines = if sort { sort_directives(lines) } else { lines };
+        section.lines = tidy_blanks(lines);
+        if i + 1 < count {
+            section.lines.push(Line::Blank);
+        }
+    }
+
+    // A comment right above the first header stays attached to it.
+    let mut preamble = tidy_blanks(unit.preamble.clone());
+    if !preamble.is_empty() && !sections.is_empty() && unit.preamble.last() == Some(&Line::Blank) {
+        preamble.push(Line::Blank);
+    }
+    UnitFile { preamble, sections }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(text: &str, sort: bool) -> String {
+        normalize(&UnitFile::parse(text).unwrap(), sort).to_string()
+    }
+
+    #[test]
+    fn sections_follow_the_usual_order() {
+        let text = "[Install]\nWantedBy=multi-user.target\n[X-Extra]\nKey=value\n\
+                    [Service]\nExecStart=/usr/bin/web\n[Unit]\nDescription=Web\n";
+        assert_eq!(
+            formatted(text, false),
+            "[Unit]\nDescription=Web\n\n[Service]\nExecStart=/usr/bin/web\n\n\
+             [X-Extra]\nKey=value\n\n[Install]\nWantedBy=multi-user.target\n"
+        );
+    }
+
+    #[test]
+    fn repeated_headers_are_merged() {
+        let text = "[Service]\nUser=web\n\n[Unit]\nDescription=Web\n\n[Service]\nGroup=web\n";
+        assert_eq!(
+            formatted(text, false),
+            "[Unit]\nDescription=Web\n\n[Service]\nUser=web\nGroup=web\n"
+        );
+    }
+
+    #[test]
+    fn blank_lines_are_collapsed() {
+        let text = "\n\n[Unit]\n\nDescription=Web\n\n\n\nAfter=network.target\n\n\n";
+        assert_eq!(
+            formatted(text, false),
+            "[Unit]\nDescription=Web\n\nAfter=network.target\n"
+        );
+    }
+
+    #[test]
+    fn comments_stay_with_their_directive() {
+        let text = "# web server\n\n[Service]\n# run as web\nUser=web\n\
+                    # the binary\nExecStart=/usr/bin/web\n# trailing   \n";
+        assert_eq!(
+            formatted(text, true),
+            "# web server\n\n[Service]\n# the binary\nExecStart=/usr/bin/web\n\
+             # run as web\nUser=web\n# trailing\n"
+        );
+        assert_eq!(
+            formatted("# about the unit\n[Unit]\nDescription=Web\n", true),
+            "# about the unit\n[Unit]\nDescription=Web\n"
+        );
+    }
+
+    #[test]
+    fn repeated_keys_keep_their_order() {
+        let text = "[Service]\nExecStartPre=/bin/b\nType=oneshot\nExecStartPre=/bin/a\n\
+                    ExecStart=/bin/c\n";
+        assert_eq!(
+            formatted(text, true),
+            "[Service]\nExecStart=/bin/c\nExecStartPre=/bin/b\nExecStartPre=/bin/a\n\
+             Type=oneshot\n"
+        );
+    }
+
+    #[test]
+    fn nothing_but_blank_lines_is_lost() {
+        let text = "; header\n[Install]\nWantedBy=a.target\n\n[Service]\n# c1\nUser=x\n\
+                    ExecStart=/bin/x \\\n  --flag\n[Unit]\nAfter=b\n\n\n[Service]\nGroup=x\n";
+        let content = |text: &str| {
+            let mut lines: Vec<String> = text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(String::from)
+                .collect();
+            lines.sort();
+            lines
+        };
+        for sort in [false, true] {
+            let mut expected = content(text);
+            expected.retain(|line| line != "[Service]");
+            expected.push("[Service]".to_string());
+            expected.sort();
+            assert_eq!(content(&formatted(text, sort)), expected);
+        }
+    }
+}
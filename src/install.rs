@@ -0,0 +1,653 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    escalate::Escalator,
+    scope::Scope,
+    systemctl::{UnitManager, UnitState},
+    unitfile::{Section, UnitFile},
+    unitpaths,
+};
+
+/// Finds `unit_name` in the search paths of `scope`, below `root` if given.
+/// Returns the path as seen from inside the root together with the contents.
+pub fn find_unit(root: Option<&Path>, scope: Scope, unit_name: &str) -> Option<(PathBuf, String)> {
+    scope.search_paths().into_iter().find_map(|dir| {
+        let path = dir.join(unit_name);
+        let contents = fs::read_to_string(unitpaths::rebase(root, &path)).ok()?;
+        Some((path, contents))
+    })
+}
+
+/// Splits `foo@bar.service` into ("foo", "bar", "service"). Templates such as
+/// `foo@.service` have an empty instance; names without `@` return `None`.
+fn split_instance(unit_name: &str) -> Option<(&str, &str, &str)> {
+    let (prefix, rest) = unit_name.split_once('@')?;
+    let (instance, suffix) = rest.rsplit_once('.')?;
+    Some((prefix, instance, suffix))
+}
+
+/// Name of the file a unit is loaded from: `foo@.service` for the instance
+/// `foo@bar.service`, the name itself otherwise.
+fn file_name(unit_name: &str) -> String {
+    match split_instance(unit_name) {
+        Some((prefix, instance, suffix)) if !instance.is_empty() => format!("{prefix}@.{suffix}"),
+        _ => unit_name.to_string(),
+    }
+}
+
+/// `.wants/` and `.requires/` directories the unit is linked into by
+/// WantedBy= and RequiredBy=.
+fn dependency_dirs(unit_dir: &Path, install: &Section) -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    for (key, suffix) in [("WantedBy", "wants"), ("RequiredBy", "requires")] {
+        for value in install.values(key) {
+            for target in value.split_whitespace() {
+                dirs.push(unit_dir.join(format!("{target}.{suffix}")));
+            }
+        }
+    }
+    dirs
+}
+
+/// Symlinks implied by the [Install] section of a unit, as (link, target) pairs.
+///
+/// WantedBy= and RequiredBy= become links in `<target>.wants/` and
+/// `<target>.requires/`, and each Alias= a link next to the unit, all inside
+/// `unit_dir` and pointing at `unit_path`.
+///
+/// Instances of a template are linked under their own name, aliases included.
+/// A template given without an instance uses its DefaultInstance= for the
+/// dependency links but keeps template aliases; without one it only gets its
+/// aliases, like `systemctl enable` does.
+pub fn install_links(
+    unit_dir: &Path,
+    unit_path: &Path,
+    unit_name: &str,
+    unit: &UnitFile,
+) -> Vec<(PathBuf, PathBuf)> {
+    let Some(install) = unit.sections.iter().find(|s| s.name == "Install") else {
+        return vec![];
+    };
+
+    let given = split_instance(unit_name)
+        .map(|(_, instance, _)| instance)
+        .filter(|instance| !instance.is_empty());
+    let instance = match split_instance(unit_name) {
+        Some((_, "", _)) => install.values("DefaultInstance").last().copied(),
+        _ => given,
+    };
+    let instantiate = |name: &str, instance: Option<&str>| match (split_instance(name), instance) {
+        (Some((prefix, "", suffix)), Some(instance)) => format!("{prefix}@{instance}.{suffix}"),
+        _ => name.to_string(),
+    };
+    let is_bare_template =
+        matches!(split_instance(unit_name), Some((_, "", _))) && instance.is_none();
+
+    let mut links = vec![];
+    if !is_bare_template {
+        for dir in dependency_dirs(unit_dir, install) {
+            links.push((
+                dir.join(instantiate(unit_name, instance)),
+                unit_path.to_path_buf(),
+            ));
+        }
+    }
+    for value in install.values("Alias") {
+        for alias in value.split_whitespace() {
+            links.push((
+                unit_dir.join(instantiate(alias, given)),
+                unit_path.to_path_buf(),
+            ));
+        }
+    }
+    links
+}
+
+/// Loads `unit_name` and its [Install] section, looking up the template for
+/// instances. Returns the unit path inside the root and the parsed unit.
+fn load_unit(
+    root: Option<&Path>,
+    scope: Scope,
+    unit_name: &str,
+) -> io::Result<(PathBuf, UnitFile)> {
+    let (unit_path, contents) = find_unit(root, scope, &file_name(unit_name))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{unit_name} not found")))?;
+    let unit =
+        UnitFile::parse(&contents).map_err(|e| io::Error::other(format!("{unit_name}: {e}")))?;
+    Ok((unit_path, unit))
+}
+
+/// Directory enable and disable create and remove links in.
+fn link_dir(root: Option<&Path>, scope: Scope) -> io::Result<PathBuf> {
+    if root.is_some() && scope == Scope::User {
+        return Err(io::Error::other(
+            "the user scope is not available below a root directory",
+        ));
+    }
+    scope
+        .unit_dir()
+        .ok_or_else(|| io::Error::other("no unit directory for this scope"))
+}
+
+/// Runs `f` for `unit_name` and every unit named by Also=, each only once.
+fn with_also(
+    root: Option<&Path>,
+    scope: Scope,
+    unit_name: &str,
+    mut f: impl FnMut(&str, &Path, &UnitFile) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut seen = HashSet::new();
+    let mut pending = vec![unit_name.to_string()];
+
+    while let Some(name) = pending.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let (unit_path, unit) = load_unit(root, scope, &name)?;
+        f(&name, &unit_path, &unit)?;
+
+        if let Some(install) = unit.sections.iter().find(|s| s.name == "Install") {
+            for value in install.values("Also") {
+                pending.extend(value.split_whitespace().map(str::to_string));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Enables a unit without a running systemd by creating its [Install]
+/// symlinks below `root`, following Also= to enable related units too.
+/// Links only root can create go through `escalator`. Returns the links that
+/// were created.
+pub fn enable(
+    mut escalator: Option<&mut (dyn Escalator + '_)>,
+    root: Option<&Path>,
+    scope: Scope,
+    unit_name: &str,
+) -> io::Result<Vec<PathBuf>> {
+    let unit_dir = link_dir(root, scope)?;
+    let mut created = vec![];
+
+    with_also(root, scope, unit_name, |name, unit_path, unit| {
+        for (link, target) in install_links(&unit_dir, unit_path, name, unit) {
+            let link = unitpaths::rebase(root, &link);
+            if create_link(escalator.as_deref_mut(), &link, &target)? {
+                created.push(link);
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(created)
+}
+
+/// Undoes `enable`: removes the [Install] symlinks of the unit and of the
+/// units named by Also=. Disabling a template also removes the links and
+/// aliases of all its instances. Returns the links that were removed.
+pub fn disable(
+    mut escalator: Option<&mut (dyn Escalator + '_)>,
+    root: Option<&Path>,
+    scope: Scope,
+    unit_name: &str,
+) -> io::Result<Vec<PathBuf>> {
+    let unit_dir = link_dir(root, scope)?;
+    let mut removed = vec![];
+
+    with_also(root, scope, unit_name, |name, unit_path, unit| {
+        let mut links: Vec<_> = install_links(&unit_dir, unit_path, name, unit)
+            .into_iter()
+            .map(|(link, _)| unitpaths::rebase(root, &link))
+            .collect();
+
+        if let (Some((prefix, "", suffix)), Some(install)) = (
+            split_instance(name),
+            unit.sections.iter().find(|s| s.name == "Install"),
+        ) {
+            // Instances are named after the template, their aliases after
+            // the template aliases; `remove_link` skips links to other units.
+            let mut patterns: Vec<_> = dependency_dirs(&unit_dir, install)
+                .into_iter()
+                .map(|dir| (dir, prefix, suffix))
+                .collect();
+            for value in install.values("Alias") {
+                for (alias_prefix, _, alias_suffix) in
+                    value.split_whitespace().filter_map(split_instance)
+                {
+                    patterns.push((unit_dir.clone(), alias_prefix, alias_suffix));
+                }
+            }
+            for (dir, prefix, suffix) in patterns {
+                let Ok(entries) = fs::read_dir(unitpaths::rebase(root, &dir)) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    if file_name.starts_with(&format!("{prefix}@"))
+                        && file_name.ends_with(&format!(".{suffix}"))
+                        && !links.contains(&entry.path())
+                    {
+                        links.push(entry.path());
+                    }
+                }
+            }
+        }
+
+        for link in links {
+            if remove_link(escalator.as_deref_mut(), &link, unit_path)? {
+                removed.push(link);
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(removed)
+}
+
+/// Shell script run as root to create a link, with the target and the link
+/// as arguments. `ln` fails rather than replace a link made in the meantime.
+const LINK_SCRIPT: &str = r#"set -e
+mkdir -p "$(dirname "$2")"
+ln -s "$1" "$2"
+"#;
+
+/// Removes `link` if it points at `target`. Returns false if there was no such link.
+fn remove_link(
+    escalator: Option<&mut (dyn Escalator + '_)>,
+    link: &Path,
+    target: &Path,
+) -> io::Result<bool> {
+    match fs::read_link(link) {
+        Ok(existing) if existing == target => {
+            match escalator {
+                Some(escalator) if escalator.needed_for(link) => {
+                    escalator.run("rm", &["-f", "--", &link.to_string_lossy()], None)?;
+                }
+                _ => fs::remove_file(link)?,
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Creates `link` pointing at `target`. Returns false if it was already there.
+fn create_link(
+    escalator: Option<&mut (dyn Escalator + '_)>,
+    link: &Path,
+    target: &Path,
+) -> io::Result<bool> {
+    match fs::read_link(link) {
+        Ok(existing) if existing == target => return Ok(false),
+        Ok(existing) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already points at {}",
+                    link.display(),
+                    existing.display()
+                ),
+            ))
+        }
+        Err(_) if link.exists() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a symlink", link.display()),
+            ))
+        }
+        Err(_) => (),
+    }
+
+    match escalator {
+        Some(escalator) if escalator.needed_for(link) => {
+            escalator.run(
+                "sh",
+                &[
+                    "-c",
+                    LINK_SCRIPT,
+                    "sh",
+                    &target.to_string_lossy(),
+                    &link.to_string_lossy(),
+                ],
+                None,
+            )?;
+        }
+        _ => {
+            if let Some(dir) = link.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            symlink(target, link)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Whether this system was booted with systemd, the same check as sd_booted(3).
+pub fn systemd_running() -> bool {
+    Path::new("/run/systemd/system").is_dir()
+}
+
+/// `UnitManager` for when there is no systemd to talk to: a staged root
+/// filesystem, a container or a chroot. Enabling and disabling create and
+/// remove the symlinks directly; anything that needs a running manager is
+/// refused.
+pub struct OfflineManager {
+    /// Root filesystem the links are created in, `None` for this one.
+    pub root: Option<PathBuf>,
+    /// Creates and removes the links only root can change.
+    pub escalator: Option<Box<dyn Escalator>>,
+}
+
+impl OfflineManager {
+    fn not_running(&self) -> io::Error {
+        match &self.root {
+            Some(root) => io::Error::other(format!(
+                "systemd is not running in {}, only enabling and disabling are possible",
+                root.display()
+            )),
+            None => {
+                io::Error::other("systemd is not running, only enabling and disabling are possible")
+            }
+        }
+    }
+}
+
+impl UnitManager for OfflineManager {
+    fn daemon_reload(&mut self, _scope: Scope) -> io::Result<()> {
+        // Nothing has loaded the staged units yet, so there is nothing to reload.
+        Ok(())
+    }
+
+    fn enable(&mut self, scope: Scope, unit: &str, now: bool) -> io::Result<()> {
+        if now {
+            return Err(self.not_running());
+        }
+        enable(
+            self.escalator.as_deref_mut(),
+            self.root.as_deref(),
+            scope,
+            unit,
+        )?;
+        Ok(())
+    }
+
+    fn disable(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        disable(
+            self.escalator.as_deref_mut(),
+            self.root.as_deref(),
+            scope,
+            unit,
+        )?;
+        Ok(())
+    }
+
+    fn start(&mut self, _scope: Scope, _unit: &str) -> io::Result<()> {
+        Err(self.not_running())
+    }
+
+    fn stop(&mut self, _scope: Scope, _unit: &str) -> io::Result<()> {
+        Err(self.not_running())
+    }
+
+    fn restart(&mut self, _scope: Scope, _unit: &str) -> io::Result<()> {
+        Err(self.not_running())
+    }
+
+    fn unit_state(&mut self, _scope: Scope, _unit: &str) -> io::Result<UnitState> {
+        Err(self.not_running())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escalate::RecordingEscalator;
+
+    const UNIT_DIR: &str = "/etc/systemd/system";
+
+    /// An empty root filesystem of its own for each test.
+    fn test_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("css-install-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(&UNIT_DIR[1..])).unwrap();
+        root
+    }
+
+    fn add_unit(root: &Path, name: &str, install: &str) {
+        let contents = format!("[Service]\nExecStart=/bin/true\n\n[Install]\n{install}");
+        fs::write(root.join(&UNIT_DIR[1..]).join(name), contents).unwrap();
+    }
+
+    /// Where `link`, relative to the unit directory, points inside the root.
+    fn target(root: &Path, link: &str) -> Option<PathBuf> {
+        fs::read_link(root.join(&UNIT_DIR[1..]).join(link)).ok()
+    }
+
+    fn unit(name: &str) -> PathBuf {
+        Path::new(UNIT_DIR).join(name)
+    }
+
+    #[test]
+    fn wanted_by_required_by_and_alias() {
+        let root = test_root("links");
+        add_unit(
+            &root,
+            "web.service",
+            "WantedBy=multi-user.target\nRequiredBy=a.target b.target\nAlias=www.service\n",
+        );
+
+        let created = enable(None, Some(&root), Scope::System, "web.service").unwrap();
+
+        assert_eq!(created.len(), 4);
+        for link in [
+            "multi-user.target.wants/web.service",
+            "a.target.requires/web.service",
+            "b.target.requires/web.service",
+            "www.service",
+        ] {
+            assert_eq!(target(&root, link), Some(unit("web.service")), "{link}");
+        }
+        // Enabling again changes nothing.
+        assert!(enable(None, Some(&root), Scope::System, "web.service")
+            .unwrap()
+            .is_empty());
+
+        let removed = disable(None, Some(&root), Scope::System, "web.service").unwrap();
+        assert_eq!(removed.len(), 4);
+        assert_eq!(target(&root, "www.service"), None);
+    }
+
+    #[test]
+    fn also_enables_the_named_units() {
+        let root = test_root("also");
+        add_unit(
+            &root,
+            "web.service",
+            "WantedBy=multi-user.target\nAlso=web.socket\n",
+        );
+        add_unit(
+            &root,
+            "web.socket",
+            "WantedBy=sockets.target\nAlso=web.service\n",
+        );
+
+        enable(None, Some(&root), Scope::System, "web.service").unwrap();
+        assert_eq!(
+            target(&root, "sockets.target.wants/web.socket"),
+            Some(unit("web.socket"))
+        );
+
+        disable(None, Some(&root), Scope::System, "web.service").unwrap();
+        assert_eq!(target(&root, "sockets.target.wants/web.socket"), None);
+        assert_eq!(target(&root, "multi-user.target.wants/web.service"), None);
+    }
+
+    #[test]
+    fn instances_link_to_their_template() {
+        let root = test_root("instance");
+        add_unit(
+            &root,
+            "getty@.service",
+            "WantedBy=getty.target\nAlias=console@.service\n",
+        );
+
+        enable(None, Some(&root), Scope::System, "getty@tty2.service").unwrap();
+
+        assert_eq!(
+            target(&root, "getty.target.wants/getty@tty2.service"),
+            Some(unit("getty@.service"))
+        );
+        assert_eq!(
+            target(&root, "console@tty2.service"),
+            Some(unit("getty@.service"))
+        );
+    }
+
+    #[test]
+    fn templates_use_their_default_instance() {
+        let root = test_root("default-instance");
+        add_unit(
+            &root,
+            "getty@.service",
+            "WantedBy=getty.target\nDefaultInstance=tty1\nAlias=console@.service\n",
+        );
+
+        enable(None, Some(&root), Scope::System, "getty@.service").unwrap();
+
+        assert_eq!(
+            target(&root, "getty.target.wants/getty@tty1.service"),
+            Some(unit("getty@.service"))
+        );
+        // The alias stays a template, as with systemctl.
+        assert_eq!(
+            target(&root, "console@.service"),
+            Some(unit("getty@.service"))
+        );
+        assert_eq!(target(&root, "console@tty1.service"), None);
+    }
+
+    #[test]
+    fn bare_templates_only_get_their_aliases() {
+        let root = test_root("bare-template");
+        add_unit(
+            &root,
+            "getty@.service",
+            "WantedBy=getty.target\nAlias=console@.service\n",
+        );
+
+        let created = enable(None, Some(&root), Scope::System, "getty@.service").unwrap();
+
+        assert_eq!(
+            created,
+            [root.join(&UNIT_DIR[1..]).join("console@.service")]
+        );
+        assert!(!root
+            .join(&UNIT_DIR[1..])
+            .join("getty.target.wants")
+            .exists());
+    }
+
+    #[test]
+    fn disabling_a_template_removes_its_instances() {
+        let root = test_root("disable-template");
+        add_unit(
+            &root,
+            "getty@.service",
+            "WantedBy=getty.target\nAlias=console@.service\n",
+        );
+        add_unit(&root, "other@.service", "WantedBy=getty.target\n");
+        for name in [
+            "getty@tty1.service",
+            "getty@tty2.service",
+            "other@tty1.service",
+        ] {
+            enable(None, Some(&root), Scope::System, name).unwrap();
+        }
+
+        let removed = disable(None, Some(&root), Scope::System, "getty@.service").unwrap();
+
+        assert_eq!(removed.len(), 4);
+        assert_eq!(target(&root, "getty.target.wants/getty@tty1.service"), None);
+        assert_eq!(target(&root, "getty.target.wants/getty@tty2.service"), None);
+        assert_eq!(target(&root, "console@tty1.service"), None);
+        assert_eq!(
+            target(&root, "getty.target.wants/other@tty1.service"),
+            Some(unit("other@.service"))
+        );
+    }
+
+    #[test]
+    fn conflicting_links_are_an_error() {
+        let root = test_root("conflict");
+        add_unit(&root, "web.service", "Alias=www.service\n");
+        symlink(
+            "/etc/systemd/system/other.service",
+            root.join(&UNIT_DIR[1..]).join("www.service"),
+        )
+        .unwrap();
+
+        let error = enable(None, Some(&root), Scope::System, "web.service").unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert!(error
+            .to_string()
+            .ends_with("already points at /etc/systemd/system/other.service"));
+        // Disabling leaves links to other units alone.
+        assert!(disable(None, Some(&root), Scope::System, "web.service")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn links_only_root_can_change_go_through_the_escalator() {
+        let root = test_root("escalated");
+        add_unit(&root, "web.service", "WantedBy=multi-user.target\n");
+        let link = root
+            .join(&UNIT_DIR[1..])
+            .join("multi-user.target.wants/web.service");
+        let mut escalator = RecordingEscalator::new(&root);
+
+        enable(
+            Some(&mut escalator),
+            Some(&root),
+            Scope::System,
+            "web.service",
+        )
+        .unwrap();
+        enable(None, Some(&root), Scope::System, "web.service").unwrap();
+        disable(
+            Some(&mut escalator),
+            Some(&root),
+            Scope::System,
+            "web.service",
+        )
+        .unwrap();
+
+        let calls: Vec<_> = escalator
+            .calls
+            .iter()
+            .map(|(program, args, _)| (program.as_str(), args.iter().map(String::as_str).collect()))
+            .collect();
+        let link = link.to_string_lossy();
+        assert_eq!(
+            calls,
+            [
+                (
+                    "sh",
+                    vec![
+                        "-c",
+                        LINK_SCRIPT,
+                        "sh",
+                        "/etc/systemd/system/web.service",
+                        &link
+                    ]
+                ),
+                ("rm", vec!["-f", "--", &link]),
+            ]
+        );
+    }
+}
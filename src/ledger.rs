@@ -0,0 +1,247 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use directories::ProjectDirs;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{scope::Scope, unitfile::UnitFile};
+
+/// A unit written by this tool, as it was when it was written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub unit: String,
+    /// Name of the template the unit was created from.
+    pub template: String,
+    /// Values the template's `{{Key}}` placeholders ended up with.
+    pub variables: BTreeMap<String, String>,
+    pub scope: Scope,
+    pub path: PathBuf,
+    /// UTC time of the write as YYYYMMDDTHHMMSS.
+    pub written_at: String,
+    /// Backup of the file the tool's first write to `path` replaced, kept
+    /// when it is written again. Later backups only hold its own versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_backup: Option<PathBuf>,
+    /// SHA-256 of the contents written, in hex.
+    pub sha256: String,
+}
+
+/// How a recorded unit compares to what is on disk now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryState {
+    Unchanged,
+    /// Edited outside the tool since it was written.
+    Modified,
+    /// Deleted outside the tool.
+    Missing,
+}
+
+impl EntryState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EntryState::Unchanged => "unchanged",
+            EntryState::Modified => "edited by hand",
+            EntryState::Missing => "deleted",
+        }
+    }
+}
+
+impl LedgerEntry {
+    pub fn state(&self) -> EntryState {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) if content_hash(&contents) == self.sha256 => EntryState::Unchanged,
+            Ok(_) => EntryState::Modified,
+            Err(_) => EntryState::Missing,
+        }
+    }
+}
+
+/// Record of every unit the tool has written, kept as JSON in the data
+/// directory, ~/.local/share/create-systemd-service/units.json.
+#[derive(Debug)]
+pub struct Ledger {
+    path: PathBuf,
+    pub entries: Vec<LedgerEntry>,
+}
+
+impl Ledger {
+    pub fn default_path() -> PathBuf {
+        let proj_dirs = ProjectDirs::from("_", "_", "create-systemd-service").unwrap();
+        proj_dirs.data_dir().join("units.json")
+    }
+
+    /// Reads the ledger at `path`, which does not have to exist yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let entries = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        Ok(Ledger {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Records a write, replacing any earlier entry for the same path but
+    /// keeping the backup its first write made.
+    pub fn record(&mut self, mut entry: LedgerEntry) -> io::Result<()> {
+        if let Some(earlier) = self.entries.iter().find(|e| e.path == entry.path) {
+            entry.original_backup = earlier.original_backup.clone();
+        }
+        self.entries.retain(|e| e.path != entry.path);
+        self.entries.push(entry);
+        self.entries.sort_by(|a, b| a.unit.cmp(&b.unit));
+        self.store()
+    }
+
+    /// Drops the entry for `path` once the unit has been removed.
+    pub fn forget(&mut self, path: &Path) -> io::Result<()> {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        if self.entries.len() == before {
+            return Ok(());
+        }
+        self.store()
+    }
+
+    fn store(&self) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.entries).map_err(io::Error::other)?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temp_path = self.path.with_extension(format!("{}.tmp", process::id()));
+        fs::write(&temp_path, json + "\n")?;
+        fs::rename(&temp_path, &self.path)
+    }
+}
+
+pub fn content_hash(contents: &str) -> String {
+    Sha256::digest(contents.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Values of the `{{Key}}` placeholders of `template` in the unit rendered
+/// from it: each placeholder takes the value of the directive it stood in.
+pub fn template_variables(template: &str, rendered: &str) -> BTreeMap<String, String> {
+    let re = Regex::new(r"\{\{(\w+)\}\}").unwrap();
+    let (Ok(template), Ok(rendered)) = (UnitFile::parse(template), UnitFile::parse(rendered))
+    else {
+        return BTreeMap::new();
+    };
+
+    let mut variables = BTreeMap::new();
+    for section in &template.sections {
+        let Some(rendered_section) = rendered.sections.iter().find(|s| s.name == section.name)
+        else {
+            continue;
+        };
+        for directive in section.directives() {
+            let Some(value) = rendered_section.values(&directive.key).last().copied() else {
+                continue;
+            };
+            for placeholder in re.captures_iter(&directive.value) {
+                variables.insert(placeholder[1].to_string(), value.to_string());
+            }
+        }
+    }
+    variables
+}
+
+/// `template` with every `{{Key}}` placeholder that has a recorded value filled in.
+pub fn fill_variables(template: &str, variables: &BTreeMap<String, String>) -> String {
+    let re = Regex::new(r"\{\{(\w+)\}\}").unwrap();
+    re.replace_all(template, |captures: &regex::Captures| {
+        variables
+            .get(&captures[1])
+            .cloned()
+            .unwrap_or_else(|| captures[0].to_string())
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("css-ledger-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(path: &Path, contents: &str, backup: Option<&str>) -> LedgerEntry {
+        LedgerEntry {
+            unit: "web.service".to_string(),
+            template: "simple.service".to_string(),
+            variables: BTreeMap::new(),
+            scope: Scope::System,
+            path: path.to_path_buf(),
+            written_at: "20240101T000000".to_string(),
+            original_backup: backup.map(PathBuf::from),
+            sha256: content_hash(contents),
+        }
+    }
+
+    #[test]
+    fn record_and_forget_persist() {
+        let dir = test_dir("persist");
+        let (ledger_path, unit) = (dir.join("units.json"), dir.join("web.service"));
+        let mut ledger = Ledger::load(&ledger_path).unwrap();
+        assert!(ledger.entries.is_empty());
+
+        ledger.record(entry(&unit, "a", Some("first.bak"))).unwrap();
+        ledger
+            .record(entry(&unit, "b", Some("second.bak")))
+            .unwrap();
+
+        let reloaded = Ledger::load(&ledger_path).unwrap();
+        assert_eq!(reloaded.entries.len(), 1);
+        assert_eq!(reloaded.entries[0].sha256, content_hash("b"));
+        assert_eq!(
+            reloaded.entries[0].original_backup,
+            Some(PathBuf::from("first.bak"))
+        );
+
+        ledger.forget(&unit).unwrap();
+        assert!(Ledger::load(&ledger_path).unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn state_compares_with_the_file_on_disk() {
+        let dir = test_dir("state");
+        let unit = dir.join("web.service");
+        let recorded = entry(&unit, "[Service]\n", None);
+
+        fs::write(&unit, "[Service]\n").unwrap();
+        assert_eq!(recorded.state(), EntryState::Unchanged);
+        fs::write(&unit, "[Service]\nUser=web\n").unwrap();
+        assert_eq!(recorded.state(), EntryState::Modified);
+        fs::remove_file(&unit).unwrap();
+        assert_eq!(recorded.state(), EntryState::Missing);
+    }
+
+    #[test]
+    fn variables_fill_the_template_back_in() {
+        let template = "[Unit]\nDescription={{Description}}\n\n\
+                        [Service]\nExecStart={{ExecStart}}\nUser={{User}}\n";
+        let rendered = "[Unit]\nDescription=Web server\n\n\
+                        [Service]\nExecStart=/usr/bin/web --port 80\nUser={{User}}\n";
+
+        let variables = template_variables(template, rendered);
+
+        assert_eq!(variables["Description"], "Web server");
+        assert_eq!(variables["ExecStart"], "/usr/bin/web --port 80");
+        assert_eq!(fill_variables(template, &variables), rendered);
+    }
+}
@@ -0,0 +1,606 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    unitfile::UnitFile,
+    unittype::UnitType,
+    verify::{Diagnostic, Severity},
+};
+
+/// How a rule's findings are reported, set per rule in the `[lint]` table of
+/// config.toml.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Off,
+    Warning,
+    Error,
+}
+
+/// The unit a rule looks at.
+pub struct LintedUnit<'a> {
+    pub unit: &'a UnitFile,
+    pub unit_type: UnitType,
+    text: &'a str,
+}
+
+impl LintedUnit<'_> {
+    /// Every value assigned to `key` in `section`, resets included.
+    pub fn values(&self, section: &str, key: &str) -> Vec<&str> {
+        self.unit
+            .sections
+            .iter()
+            .filter(|s| s.name == section)
+            .flat_map(|s| s.values(key))
+            .collect()
+    }
+
+    /// The values of a list directive such as After=, split on whitespace
+    /// and with resets applied.
+    pub fn list(&self, section: &str, key: &str) -> Vec<&str> {
+        let mut items = vec![];
+        for value in self.values(section, key) {
+            if value.is_empty() {
+                items.clear();
+            }
+            items.extend(value.split_whitespace());
+        }
+        items
+    }
+
+    /// The value a single-valued directive ends up with: the last one.
+    pub fn last(&self, section: &str, key: &str) -> Option<&str> {
+        self.values(section, key).last().copied()
+    }
+
+    /// Every value assigned to `key` in `section` with the 1-based line it
+    /// starts on, resets included.
+    pub fn assignments(&self, section: &str, key: &str) -> Vec<(Option<usize>, &str)> {
+        let lines = self.assignment_lines(section, key);
+        self.values(section, key)
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| (lines.get(index).copied(), value))
+            .collect()
+    }
+
+    /// 1-based line of the last assignment of `key` in `section`, or of the
+    /// section header when `key` is `None`.
+    pub fn line(&self, section: &str, key: Option<&str>) -> Option<usize> {
+        match key {
+            Some(key) => self.assignment_lines(section, key).last().copied(),
+            None => self
+                .text
+                .lines()
+                .position(|line| section_header(line) == Some(section))
+                .map(|index| index + 1),
+        }
+    }
+
+    /// 1-based lines assigning `key` in `section`, in order. Continuation lines
+    /// are skipped the way `UnitFile::parse` joins them.
+    fn assignment_lines(&self, section: &str, key: &str) -> Vec<usize> {
+        let mut current = None;
+        let mut continued = false;
+        let mut lines = vec![];
+        for (index, raw) in self.text.lines().enumerate() {
+            if continued {
+                continued = raw.ends_with('\\');
+                continue;
+            }
+            let line = raw.trim();
+            if let Some(name) = section_header(line) {
+                current = Some(name);
+            } else if let Some((found_key, _)) = line.split_once('=') {
+                continued = line.ends_with('\\');
+                if current == Some(section) && found_key.trim() == key {
+                    lines.push(index + 1);
+                }
+            }
+        }
+        lines
+    }
+}
+
+/// Name of the section `line` opens, if it is a section header.
+fn section_header(line: &str) -> Option<&str> {
+    line.trim().strip_prefix('[')?.strip_suffix(']')
+}
+
+/// A problem a rule found: where, and what is wrong.
+pub struct Finding {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// A best-practice check over a parsed unit. Rules only report; whether a
+/// finding is a warning, an error or ignored is up to the config.
+pub trait Rule {
+    /// Stable identifier, used in the config and printed with each finding.
+    fn id(&self) -> &'static str;
+    fn summary(&self) -> &'static str;
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Warning
+    }
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding>;
+}
+
+/// Runs the rules over units, at the levels the config sets.
+pub struct Linter {
+    rules: Vec<Box<dyn Rule>>,
+    levels: BTreeMap<String, LintLevel>,
+}
+
+impl Linter {
+    /// The built-in rules, with `levels` overriding their default levels.
+    pub fn new(levels: BTreeMap<String, LintLevel>) -> Self {
+        Linter {
+            rules: builtin_rules(),
+            levels,
+        }
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = (&dyn Rule, LintLevel)> {
+        self.rules
+            .iter()
+            .map(|rule| (rule.as_ref(), self.level(rule.as_ref())))
+    }
+
+    fn level(&self, rule: &dyn Rule) -> LintLevel {
+        self.levels
+            .get(rule.id())
+            .copied()
+            .unwrap_or_else(|| rule.default_level())
+    }
+
+    /// Findings for the unit in `text`; a unit that does not parse has none,
+    /// the parse error is reported elsewhere.
+    pub fn lint(&self, text: &str, unit_type: UnitType) -> Vec<Diagnostic> {
+        let Ok(unit) = UnitFile::parse(text) else {
+            return vec![];
+        };
+        let linted = LintedUnit {
+            unit: &unit,
+            unit_type,
+            text,
+        };
+
+        let mut diagnostics = vec![];
+        for (rule, level) in self.rules() {
+            let severity = match level {
+                LintLevel::Off => continue,
+                LintLevel::Warning => Severity::Warning,
+                LintLevel::Error => Severity::Error,
+            };
+            diagnostics.extend(rule.check(&linted).into_iter().map(|finding| Diagnostic {
+                line: finding.line,
+                severity,
+                message: finding.message,
+                rule: Some(rule.id()),
+            }));
+        }
+        diagnostics
+    }
+}
+
+/// Ids of the built-in rules, for checking the config.
+pub fn rule_ids() -> Vec<&'static str> {
+    builtin_rules().iter().map(|rule| rule.id()).collect()
+}
+
+fn builtin_rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(MissingDescription),
+        Box::new(RelativeExec),
+        Box::new(ForkingWithoutPidFile),
+        Box::new(RestartWithoutDelay),
+        Box::new(NetworkOnlineWithoutWants),
+        Box::new(MissingWantedBy),
+        Box::new(ExplicitRootUser),
+        Box::new(DeprecatedDirective),
+    ]
+}
+
+struct MissingDescription;
+
+impl Rule for MissingDescription {
+    fn id(&self) -> &'static str {
+        "missing-description"
+    }
+
+    fn summary(&self) -> &'static str {
+        "[Unit] has no Description="
+    }
+
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding> {
+        match unit.last("Unit", "Description") {
+            Some(description) if !description.trim().is_empty() => vec![],
+            _ => vec![Finding {
+                line: unit.line("Unit", None),
+                message: "no Description=, systemctl and the journal show the bare unit name"
+                    .to_string(),
+            }],
+        }
+    }
+}
+
+struct RelativeExec;
+
+impl Rule for RelativeExec {
+    fn id(&self) -> &'static str {
+        "exec-not-absolute"
+    }
+
+    fn summary(&self) -> &'static str {
+        "ExecStart= and the other Exec lines should name the program by absolute path"
+    }
+
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding> {
+        let Some(section) = unit.unit_type.type_section() else {
+            return vec![];
+        };
+        let keys = [
+            "ExecStartPre",
+            "ExecStart",
+            "ExecStartPost",
+            "ExecReload",
+            "ExecStop",
+            "ExecStopPost",
+            "ExecCondition",
+        ];
+        keys.iter()
+            .flat_map(|key| {
+                unit.assignments(section, key)
+                    .into_iter()
+                    .filter(|(_, value)| {
+                        // Special prefixes such as "-" or "!!" come before the path.
+                        let program = value.trim_start_matches(['@', '-', ':', '+', '!']);
+                        !program.is_empty() && !program.starts_with(['/', '%'])
+                    })
+                    .map(move |(line, value)| Finding {
+                        line,
+                        message: format!("{key}={value} does not start with an absolute path"),
+                    })
+            })
+            .collect()
+    }
+}
+
+struct ForkingWithoutPidFile;
+
+impl Rule for ForkingWithoutPidFile {
+    fn id(&self) -> &'static str {
+        "forking-without-pidfile"
+    }
+
+    fn summary(&self) -> &'static str {
+        "Type=forking without PIDFile="
+    }
+
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding> {
+        if unit.last("Service", "Type") != Some("forking")
+            || unit.last("Service", "PIDFile").is_some()
+        {
+            return vec![];
+        }
+        vec![Finding {
+            line: unit.line("Service", Some("Type")),
+            message: "Type=forking without PIDFile=, systemd has to guess the main process"
+                .to_string(),
+        }]
+    }
+}
+
+struct RestartWithoutDelay;
+
+impl Rule for RestartWithoutDelay {
+    fn id(&self) -> &'static str {
+        "restart-without-delay"
+    }
+
+    fn summary(&self) -> &'static str {
+        "Restart=always without RestartSec="
+    }
+
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding> {
+        if unit.last("Service", "Restart") != Some("always")
+            || unit.last("Service", "RestartSec").is_some()
+        {
+            return vec![];
+        }
+        vec![Finding {
+            line: unit.line("Service", Some("Restart")),
+            message: "Restart=always without RestartSec=, a crashing service restarts every 100ms \
+                      until it hits the start limit"
+                .to_string(),
+        }]
+    }
+}
+
+struct NetworkOnlineWithoutWants;
+
+impl Rule for NetworkOnlineWithoutWants {
+    fn id(&self) -> &'static str {
+        "network-online-without-wants"
+    }
+
+    fn summary(&self) -> &'static str {
+        "After=network-online.target without Wants=network-online.target"
+    }
+
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding> {
+        const TARGET: &str = "network-online.target";
+        let pulled_in = ["Wants", "Requires", "BindsTo"]
+            .iter()
+            .any(|key| unit.list("Unit", key).contains(&TARGET));
+        if !unit.list("Unit", "After").contains(&TARGET) || pulled_in {
+            return vec![];
+        }
+        vec![Finding {
+            line: unit.line("Unit", Some("After")),
+            message: format!(
+                "After={TARGET} without Wants={TARGET} only orders the unit, nothing starts the target"
+            ),
+        }]
+    }
+}
+
+struct MissingWantedBy;
+
+impl Rule for MissingWantedBy {
+    fn id(&self) -> &'static str {
+        "missing-wanted-by"
+    }
+
+    fn summary(&self) -> &'static str {
+        "[Install] has nothing for systemctl enable to do"
+    }
+
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding> {
+        // Units without [Install] are meant to be started by something else.
+        let Some(header) = unit.line("Install", None) else {
+            return vec![];
+        };
+        let installs = ["WantedBy", "RequiredBy", "UpheldBy", "Alias", "Also"]
+            .iter()
+            .any(|key| !unit.list("Install", key).is_empty());
+        if installs {
+            return vec![];
+        }
+        vec![Finding {
+            line: Some(header),
+            message: "[Install] has no WantedBy=, enabling the unit does nothing".to_string(),
+        }]
+    }
+}
+
+struct ExplicitRootUser;
+
+impl Rule for ExplicitRootUser {
+    fn id(&self) -> &'static str {
+        "user-root"
+    }
+
+    fn summary(&self) -> &'static str {
+        "User=root where it changes nothing, system services run as root anyway"
+    }
+
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding> {
+        let Some(section) = unit.unit_type.type_section() else {
+            return vec![];
+        };
+        let users = unit.values(section, "User");
+        let Some((last, earlier)) = users.split_last() else {
+            return vec![];
+        };
+        // User=root does something when it overrides another user, or when
+        // PAMName= opens a session for whoever User= names.
+        let overrides = earlier
+            .iter()
+            .any(|user| !matches!(*user, "" | "root" | "0"));
+        if !matches!(*last, "root" | "0") || overrides || unit.last(section, "PAMName").is_some() {
+            return vec![];
+        }
+        vec![Finding {
+            line: unit.line(section, Some("User")),
+            message: format!(
+                "User={last} changes nothing, consider a dedicated user or DynamicUser=yes"
+            ),
+        }]
+    }
+}
+
+/// Directives systemd still reads but has replaced, with what replaced them.
+const DEPRECATED: [(&str, &str, &str); 9] = [
+    (
+        "Service",
+        "PermissionsStartOnly",
+        "the \"+\" prefix on Exec lines",
+    ),
+    ("Service", "MemoryLimit", "MemoryMax="),
+    ("Service", "CPUShares", "CPUWeight="),
+    ("Service", "StartupCPUShares", "StartupCPUWeight="),
+    ("Service", "BlockIOWeight", "IOWeight="),
+    (
+        "Service",
+        "StartLimitInterval",
+        "StartLimitIntervalSec= in [Unit]",
+    ),
+    ("Service", "StartLimitBurst", "StartLimitBurst= in [Unit]"),
+    ("Unit", "OnFailureIsolate", "OnFailureJobMode=isolate"),
+    ("Unit", "StartLimitInterval", "StartLimitIntervalSec="),
+];
+
+struct DeprecatedDirective;
+
+impl Rule for DeprecatedDirective {
+    fn id(&self) -> &'static str {
+        "deprecated-directive"
+    }
+
+    fn summary(&self) -> &'static str {
+        "Directives systemd has replaced"
+    }
+
+    fn check(&self, unit: &LintedUnit) -> Vec<Finding> {
+        DEPRECATED
+            .iter()
+            .filter(|(section, key, _)| !unit.values(section, key).is_empty())
+            .map(|(section, key, replacement)| Finding {
+                line: unit.line(section, Some(key)),
+                message: format!("{key}= is deprecated, use {replacement}"),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit no rule has anything to say about.
+    const CLEAN: &str = "[Unit]\nDescription=Web\n\n\
+                         [Service]\nExecStart=/usr/bin/web\n\n\
+                         [Install]\nWantedBy=multi-user.target\n";
+
+    fn lint(levels: &[(&str, LintLevel)], text: &str) -> Vec<Diagnostic> {
+        let levels = levels
+            .iter()
+            .map(|(id, level)| (id.to_string(), *level))
+            .collect();
+        Linter::new(levels).lint(text, UnitType::Service)
+    }
+
+    /// Rule ids and lines of the findings for `text` at the default levels.
+    fn findings(text: &str) -> Vec<(&'static str, Option<usize>)> {
+        lint(&[], text)
+            .into_iter()
+            .map(|d| (d.rule.unwrap(), d.line))
+            .collect()
+    }
+
+    /// `CLEAN` with `directive` added to `section`.
+    fn with(section: &str, directive: &str) -> String {
+        CLEAN.replace(
+            &format!("[{section}]\n"),
+            &format!("[{section}]\n{directive}\n"),
+        )
+    }
+
+    #[test]
+    fn clean_units_have_no_findings() {
+        assert_eq!(findings(CLEAN), []);
+    }
+
+    #[test]
+    fn missing_description() {
+        let text = CLEAN.replace("Description=Web", "Description=");
+        assert_eq!(findings(&text), [("missing-description", Some(1))]);
+    }
+
+    #[test]
+    fn exec_not_absolute() {
+        let text = with(
+            "Service",
+            "ExecStartPre=-mkdir /run/web\nExecStartPre=/bin/true\nExecStartPre=+chown web /run/web",
+        );
+        assert_eq!(
+            findings(&text),
+            [
+                ("exec-not-absolute", Some(5)),
+                ("exec-not-absolute", Some(7))
+            ]
+        );
+        assert_eq!(findings(&with("Service", "ExecStop=!!%h/bin/stop")), []);
+    }
+
+    #[test]
+    fn forking_without_pidfile() {
+        let text = with("Service", "Type=forking");
+        assert_eq!(findings(&text), [("forking-without-pidfile", Some(5))]);
+        assert_eq!(
+            findings(&with("Service", "Type=forking\nPIDFile=/run/web.pid")),
+            []
+        );
+    }
+
+    #[test]
+    fn restart_without_delay() {
+        let text = with("Service", "Restart=always");
+        assert_eq!(findings(&text), [("restart-without-delay", Some(5))]);
+        assert_eq!(
+            findings(&with("Service", "Restart=always\nRestartSec=5")),
+            []
+        );
+        assert_eq!(findings(&with("Service", "Restart=on-failure")), []);
+    }
+
+    #[test]
+    fn network_online_without_wants() {
+        let text = with("Unit", "After=network-online.target");
+        assert_eq!(findings(&text), [("network-online-without-wants", Some(2))]);
+        let wanted = with(
+            "Unit",
+            "After=network-online.target\nWants=network-online.target",
+        );
+        assert_eq!(findings(&wanted), []);
+    }
+
+    #[test]
+    fn missing_wanted_by() {
+        let text = CLEAN.replace("WantedBy=multi-user.target\n", "");
+        assert_eq!(findings(&text), [("missing-wanted-by", Some(7))]);
+        let without_install = CLEAN.replace("\n[Install]\nWantedBy=multi-user.target\n", "");
+        assert_eq!(findings(&without_install), []);
+        let aliased = CLEAN.replace("WantedBy=multi-user.target", "Alias=www.service");
+        assert_eq!(findings(&aliased), []);
+    }
+
+    #[test]
+    fn user_root_only_when_it_changes_nothing() {
+        assert_eq!(
+            findings(&with("Service", "User=root")),
+            [("user-root", Some(5))]
+        );
+        assert_eq!(findings(&with("Service", "User=web")), []);
+        assert_eq!(findings(&with("Service", "User=web\nUser=root")), []);
+        assert_eq!(findings(&with("Service", "User=root\nPAMName=login")), []);
+    }
+
+    #[test]
+    fn deprecated_directive() {
+        let text = with("Service", "MemoryLimit=1G");
+        assert_eq!(findings(&text), [("deprecated-directive", Some(5))]);
+        assert_eq!(findings(&with("Service", "MemoryMax=1G")), []);
+    }
+
+    #[test]
+    fn levels_override_the_defaults() {
+        let text = with("Service", "User=root\nRestart=always");
+
+        let diagnostics = lint(
+            &[
+                ("user-root", LintLevel::Off),
+                ("restart-without-delay", LintLevel::Error),
+            ],
+            &text,
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].rule, Some("restart-without-delay"));
+        assert!(diagnostics[0].severity == Severity::Error);
+        assert!(lint(&[], &text)
+            .iter()
+            .all(|d| d.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn lines_skip_continuations() {
+        let text = with(
+            "Service",
+            "ExecStartPre=/bin/echo a \\\n  ExecStartPre=b\nExecStartPre=echo c",
+        );
+        assert_eq!(findings(&text), [("exec-not-absolute", Some(7))]);
+    }
+}
@@ -1,36 +1,58 @@
 mod app;
+mod cli;
 mod command;
+mod config;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod diff;
+mod dropin;
 mod editinglist;
+mod escalate;
+mod format;
+mod install;
+mod ledger;
+mod lint;
+mod manifest;
+mod mixin;
+mod remove;
+mod scope;
+mod spec;
 mod statefullist;
+mod status;
 mod syntax;
+mod systemctl;
 mod ui;
+mod unitfile;
+mod unitpaths;
+mod unittype;
+mod verify;
+mod writer;
 
 use app::App;
 use ui::ui;
 
-use crossterm::event::{self, KeyModifiers};
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture, Event, KeyCode};
+use clap::Parser;
+use cli::{Cli, Command};
+use config::{Config, Verification};
+use crossterm::event;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture, Event};
 use crossterm::execute;
 use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
 
-use dialoguer::theme::ColorfulTheme;
-use dialoguer::Confirm;
-
 use directories::ProjectDirs;
-use nix::unistd::Uid;
 use std::error::Error;
 use std::io;
 use std::path::PathBuf;
 use std::process::exit;
+use std::time::Duration;
 
 use tui::backend::{Backend, CrosstermBackend};
 use tui::Terminal;
 
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 
-fn check_if_root_user() -> bool {
-    Uid::effective().is_root()
-}
+/// How long to wait for a key press before letting the app refresh itself.
+const TICK_RATE: Duration = Duration::from_millis(250);
 
 fn check_if_config_dir_exists(dir: Option<&str>) -> Option<bool> {
     let app = dir.unwrap_or("");
@@ -50,17 +72,6 @@ fn check_if_systemd_config_dir_exists() -> Option<bool> {
 }
 
 fn prerequisites() {
-    if !check_if_root_user() {
-        if Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt("WARNING: User does not have root privileges. This will mean you cannot write to /etc/system/system. Start program as root if you want to write to this location. Quit?")
-            .default(true)
-            .interact()
-            .unwrap()
-        {
-            exit(0);
-        }
-    }
-
     if let Some(does_config_exist) = check_if_config_dir_exists(None) {
         if !does_config_exist {
             eprintln!("~/.config dir does not exist. Reinstall this program. Bye bye.");
@@ -78,19 +89,19 @@ fn prerequisites() {
     }
 }
 
-fn start_app<B: Backend>(terminal: &mut Terminal<B>) -> io::Result<()> {
-    let mut app = App::new();
-
+fn start_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
     loop {
+        if escalate::take_suspended() {
+            terminal.clear()?;
+        }
         terminal.draw(|f| ui(f, &mut app))?;
 
-        if let Event::Key(key) = event::read()? {
-            if key.modifiers == KeyModifiers::CONTROL {
-                if let KeyCode::Char('x') = key.code {
-                    return Ok(());
-                }
-            }
+        if !event::poll(TICK_RATE)? {
+            app.on_tick();
+            continue;
+        }
 
+        if let Event::Key(key) = event::read()? {
             if !app.handle_keyboard(key) {
                 return Ok(());
             }
@@ -99,9 +110,58 @@ fn start_app<B: Backend>(terminal: &mut Terminal<B>) -> io::Result<()> {
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let mut cli = Cli::parse();
+    cli.config = match Config::load(&Config::default_path()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {e}");
+            exit(cli::EXIT_USAGE);
+        }
+    };
+    // Flags given on the command line win over the config file.
+    if cli.escalate.is_some() {
+        cli.config.escalate = cli.escalate.clone();
+    }
+    if cli.block_on_verify_errors {
+        cli.config.verify = Verification::Block;
+    }
+    if let Some(theme) = &cli.config.theme {
+        if let Err(e) = syntax::set_theme(theme) {
+            eprintln!("error: {e}");
+            exit(cli::EXIT_USAGE);
+        }
+    }
+
+    if let Some(root) = &cli.root {
+        if !root.is_dir() {
+            eprintln!("{} is not a directory.", root.display());
+            exit(cli::EXIT_USAGE);
+        }
+    }
+
+    let unit_path = match &cli.command {
+        None => None,
+        Some(Command::Edit { unit }) => match unitpaths::resolve_unit(cli.root.as_deref(), unit) {
+            Some(path) => Some(path),
+            None => {
+                eprintln!("error: no unit file named {unit}");
+                exit(cli::EXIT_USAGE);
+            }
+        },
+        Some(command) => exit(cli::run(&cli, command)),
+    };
+
     // first things first
     prerequisites();
 
+    let mut app = App::new(cli.root, cli.config);
+    if let Some(path) = unit_path {
+        if let Err(e) = app.open_unit_file(&path) {
+            eprintln!("error: unable to open {}: {e}", path.display());
+            exit(cli::EXIT_USAGE);
+        }
+    }
+
     //setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -109,7 +169,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let res = start_app(&mut terminal);
+    let res = start_app(&mut terminal, app);
 
     // restore terminal
     disable_raw_mode()?;
@@ -0,0 +1,71 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::{scope::Scope, spec::UnitSpec};
+
+/// Units to set up together, read by the `apply` subcommand.
+///
+/// ```yaml
+/// scope: system
+/// units:
+///   - template: web.service
+///     name: api
+///     variables: { ExecStart: /usr/bin/api }
+///     enable: true
+///   - spec: { name: cleanup, exec: /usr/bin/cleanup, timers: [{ on_calendar: [daily] }] }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Scope for units that do not name their own.
+    #[serde(default)]
+    pub scope: Option<Scope>,
+    pub units: Vec<ManifestUnit>,
+}
+
+/// One entry of a manifest: a template with its variables, or an inline spec.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManifestUnit {
+    /// Template file name, as printed by list-templates.
+    pub template: Option<String>,
+    /// Unit name for a template, with or without the type suffix.
+    pub name: Option<String>,
+    /// Values for the template's `{{KEY}}` placeholders.
+    pub variables: BTreeMap<String, String>,
+    pub mixins: Vec<String>,
+    pub spec: Option<UnitSpec>,
+    pub scope: Option<Scope>,
+    pub enable: bool,
+    pub start: bool,
+}
+
+/// Reads a manifest, as JSON for `.json` files and YAML otherwise.
+pub fn parse_manifest(path: &Path, text: &str) -> Result<Manifest, String> {
+    let manifest: Manifest = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(text).map_err(|e| e.to_string())?
+    } else {
+        serde_yaml::from_str(text).map_err(|e| e.to_string())?
+    };
+
+    for (i, unit) in manifest.units.iter().enumerate() {
+        let entry = i + 1;
+        match (&unit.template, &unit.name, &unit.spec) {
+            (Some(_), Some(_), None) => (),
+            (Some(_), None, None) => return Err(format!("unit {entry}: a template needs a name")),
+            (None, _, Some(_)) if unit.name.is_some() => {
+                return Err(format!("unit {entry}: a spec carries its own name"))
+            }
+            (None, None, Some(_)) => {
+                if !unit.variables.is_empty() || !unit.mixins.is_empty() {
+                    return Err(format!(
+                        "unit {entry}: variables and mixins only apply to templates"
+                    ));
+                }
+            }
+            _ => return Err(format!("unit {entry}: give either a template or a spec")),
+        }
+    }
+    Ok(manifest)
+}
@@ -0,0 +1,211 @@
+use std::{fs, path::Path};
+
+use crate::unitfile::{is_list_directive, Line, ParseError, UnitFile};
+
+/// Fragments shipped with the program. A file with the same name in the
+/// mixins directory replaces the built-in one.
+const BUILTIN_MIXINS: [(&str, &str); 4] = [
+    (
+        "sandboxing-baseline",
+        "[Service]
+NoNewPrivileges=yes
+PrivateTmp=yes
+PrivateDevices=yes
+ProtectSystem=strict
+ProtectHome=yes
+ProtectKernelTunables=yes
+ProtectKernelModules=yes
+ProtectControlGroups=yes
+RestrictSUIDSGID=yes
+LockPersonality=yes
+",
+    ),
+    (
+        "restart-on-failure",
+        "[Unit]
+StartLimitIntervalSec=300
+StartLimitBurst=5
+
+[Service]
+Restart=on-failure
+RestartSec=5s
+RestartSteps=5
+RestartMaxDelaySec=60s
+",
+    ),
+    (
+        "dynamic-user",
+        "[Service]
+User=
+Group=
+DynamicUser=yes
+",
+    ),
+    (
+        "journal-logging",
+        "[Service]
+StandardOutput=journal
+StandardError=journal
+",
+    ),
+];
+
+/// Built-in mixins plus every `*.conf` file in `dir`, as (name, contents) sorted by name.
+pub fn find_mixins(dir: &Path) -> Vec<(String, String)> {
+    let mut mixins: Vec<(String, String)> = BUILTIN_MIXINS
+        .iter()
+        .map(|(name, contents)| (name.to_string(), contents.to_string()))
+        .collect();
+
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let Ok(file_name) = entry.file_name().into_string() else {
+                continue;
+            };
+            let Some(name) = file_name.strip_suffix(".conf") else {
+                continue;
+            };
+            let Ok(contents) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            match mixins.iter_mut().find(|(existing, _)| existing == name) {
+                Some(builtin) => builtin.1 = contents,
+                None => mixins.push((name.to_string(), contents)),
+            }
+        }
+    }
+
+    mixins.sort_by(|a, b| a.0.cmp(&b.0));
+    mixins
+}
+
+/// Layers `mixin` onto `base`, section by section.
+///
+/// Conflicts are resolved the way a systemd drop-in would resolve them:
+/// * a single-valued directive in the mixin replaces the base value;
+/// * a list directive is appended, skipping values the base already has;
+/// * an empty assignment (`Key=`) removes the directive from the base, or
+///   clears the list before any values that follow it in the mixin.
+///
+/// Sections the base does not have are appended.
+pub fn apply_mixin(base: &mut UnitFile, mixin: &UnitFile) {
+    for mixin_section in &mixin.sections {
+        let section = base.section_or_insert(&mixin_section.name);
+
+        for line in &mixin_section.lines {
+            let Line::Directive(directive) = line else {
+                continue;
+            };
+            let key = directive.key.as_str();
+            let value = directive.value.as_str();
+
+            if value.is_empty() {
+                section.remove(key);
+            } else if is_list_directive(key) {
+                if !section.values(key).contains(&value) {
+                    section.push(key, value);
+                }
+            } else {
+                section.set(key, value);
+            }
+        }
+    }
+}
+
+/// Parses `template` and applies each mixin in order, later mixins winning.
+pub fn apply_mixins(template: &str, mixins: &[&str]) -> Result<String, ParseError> {
+    let mut unit = UnitFile::parse(template)?;
+    for mixin in mixins {
+        apply_mixin(&mut unit, &UnitFile::parse(mixin)?);
+    }
+    Ok(unit.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    const BASE: &str = "[Unit]\nDescription=Web\nAfter=network.target\n\n\
+                        [Service]\nExecStart=/usr/bin/web\nUser=web\nRestart=always\n";
+
+    fn mixed(mixin: &str) -> String {
+        apply_mixins(BASE, &[mixin]).unwrap()
+    }
+
+    #[test]
+    fn single_values_are_replaced() {
+        assert_eq!(
+            mixed("[Service]\nRestart=on-failure\n"),
+            BASE.replace("Restart=always", "Restart=on-failure")
+        );
+    }
+
+    #[test]
+    fn lists_are_appended_without_duplicates() {
+        assert_eq!(
+            mixed("[Unit]\nAfter=network.target\nAfter=local-fs.target\n"),
+            BASE.replace(
+                "After=network.target",
+                "After=network.target\nAfter=local-fs.target"
+            )
+        );
+    }
+
+    #[test]
+    fn empty_assignments_remove_the_directive() {
+        assert_eq!(mixed("[Service]\nUser=\n"), BASE.replace("User=web\n", ""));
+        assert_eq!(
+            mixed("[Unit]\nAfter=\nAfter=local-fs.target\n"),
+            BASE.replace("After=network.target", "After=local-fs.target")
+        );
+    }
+
+    #[test]
+    fn new_sections_are_appended() {
+        assert_eq!(
+            mixed("[Install]\nWantedBy=multi-user.target\n"),
+            format!("{BASE}\n[Install]\nWantedBy=multi-user.target\n")
+        );
+    }
+
+    #[test]
+    fn later_mixins_win() {
+        let unit = apply_mixins(
+            BASE,
+            &["[Service]\nRestart=on-failure\n", "[Service]\nRestart=no\n"],
+        )
+        .unwrap();
+        assert_eq!(unit, BASE.replace("Restart=always", "Restart=no"));
+    }
+
+    #[test]
+    fn mixin_files_replace_builtins_of_the_same_name() {
+        let dir = std::env::temp_dir().join(format!("css-mixins-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("journal-logging.conf"),
+            "[Service]\nStandardOutput=null\n",
+        )
+        .unwrap();
+        fs::write(dir.join("audit.conf"), "[Service]\nNoNewPrivileges=yes\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a mixin").unwrap();
+
+        let mixins = find_mixins(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<&str> = mixins.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "audit",
+                "dynamic-user",
+                "journal-logging",
+                "restart-on-failure",
+                "sandboxing-baseline"
+            ]
+        );
+        assert_eq!(mixins[2].1, "[Service]\nStandardOutput=null\n");
+    }
+}
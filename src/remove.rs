@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use crate::scope::Scope;
+
+/// One step of removing an installed unit. The whole list is shown for
+/// confirmation before any of it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemovalStep {
+    Stop,
+    /// Removes the [Install] symlinks.
+    Disable,
+    /// Removes the `<unit>.d` drop-in directory.
+    RemoveDropins(PathBuf),
+    RemoveUnit(PathBuf),
+    /// Puts back the version saved before the tool first wrote the unit.
+    RestoreBackup {
+        backup: PathBuf,
+        path: PathBuf,
+    },
+    /// Deletes backups of versions the tool wrote itself.
+    RemoveBackups(Vec<PathBuf>),
+    DaemonReload,
+}
+
+impl RemovalStep {
+    pub fn describe(&self, unit: &str) -> String {
+        match self {
+            RemovalStep::Stop => format!("Stop {unit}"),
+            RemovalStep::Disable => format!("Disable {unit}, removing its [Install] symlinks"),
+            RemovalStep::RemoveDropins(dir) => format!("Delete drop-ins in {}", dir.display()),
+            RemovalStep::RemoveUnit(path) => format!("Delete {}", path.display()),
+            RemovalStep::RestoreBackup { backup, path } => {
+                format!("Restore {} as {}", backup.display(), path.display())
+            }
+            RemovalStep::RemoveBackups(backups) => match backups.as_slice() {
+                [backup] => format!("Delete the backup {}", backup.display()),
+                _ => format!("Delete {} backups of earlier versions", backups.len()),
+            },
+            RemovalStep::DaemonReload => "Reload the service manager".to_string(),
+        }
+    }
+}
+
+/// A planned removal waiting for confirmation.
+pub struct Removal {
+    pub unit: String,
+    pub scope: Scope,
+    pub steps: Vec<RemovalStep>,
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use nix::unistd::Uid;
+use serde::{Deserialize, Serialize};
+
+use crate::unitpaths;
+
+/// Where a unit is installed, mirroring systemctl's --system, --user and --global.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    /// /etc/systemd/system, managed by the system manager.
+    System,
+    /// $XDG_CONFIG_HOME/systemd/user, for the current user only.
+    User,
+    /// /etc/systemd/user, the user manager of every user.
+    GlobalUser,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::System, Scope::User, Scope::GlobalUser];
+
+    /// System scope for root, user scope for everyone else.
+    pub fn default_for_current_user() -> Self {
+        if Uid::effective().is_root() {
+            Scope::System
+        } else {
+            Scope::User
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::System => "system",
+            Scope::User => "user",
+            Scope::GlobalUser => "global user",
+        }
+    }
+
+    /// Directory units of this scope are written to. `None` when the user
+    /// scope is requested but neither $XDG_CONFIG_HOME nor $HOME is set.
+    pub fn unit_dir(&self) -> Option<PathBuf> {
+        match self {
+            Scope::System => Some(PathBuf::from("/etc/systemd/system")),
+            Scope::User => unitpaths::config_home().map(|dir| dir.join("systemd/user")),
+            Scope::GlobalUser => Some(PathBuf::from("/etc/systemd/user")),
+        }
+    }
+
+    /// Directories units of this scope are loaded from, highest priority first.
+    pub fn search_paths(&self) -> Vec<PathBuf> {
+        match self {
+            Scope::System => unitpaths::SYSTEM_UNIT_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+            Scope::User => unitpaths::user_unit_paths(),
+            Scope::GlobalUser => unitpaths::GLOBAL_USER_UNIT_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,634 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mixin,
+    unitfile::{Directive, Line, Section, UnitFile},
+    unittype::UnitType,
+};
+
+/// A unit described as structured data, for deployment tooling that already
+/// keeps its services in YAML or JSON.
+///
+/// The named fields cover what services usually set; anything else goes into
+/// `extra`, so every unit the parser accepts can be exported and generated
+/// again with the same meaning.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnitSpec {
+    /// Unit name, with or without the type suffix.
+    pub name: String,
+    #[serde(skip_serializing_if = "is_service")]
+    pub kind: UnitType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Service Type=, e.g. "simple", "notify" or "oneshot".
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub service_type: Option<String>,
+    /// ExecStart= command line.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+    /// Restart= policy, e.g. "on-failure".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart_sec: Option<String>,
+    #[serde(skip_serializing_if = "Dependencies::is_empty")]
+    pub dependencies: Dependencies,
+    /// Name of a `sandboxing-*` mixin to layer onto the service.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hardening: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub wanted_by: Vec<String>,
+    /// Timers that start the service, each generated as its own unit.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timers: Vec<TimerSpec>,
+    /// Remaining directives as `Key=Value` lines, by section name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dependencies {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub wants: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+}
+
+impl Dependencies {
+    fn is_empty(&self) -> bool {
+        self.after.is_empty()
+            && self.before.is_empty()
+            && self.wants.is_empty()
+            && self.requires.is_empty()
+    }
+
+    fn fields(&mut self) -> [(&'static str, &mut Vec<String>); 4] {
+        [
+            ("After", &mut self.after),
+            ("Before", &mut self.before),
+            ("Wants", &mut self.wants),
+            ("Requires", &mut self.requires),
+        ]
+    }
+}
+
+/// A .timer unit that activates the service it belongs to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimerSpec {
+    /// Timer unit name, the service's name by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_calendar: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_boot_sec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_unit_active_sec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persistent: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub randomized_delay_sec: Option<String>,
+}
+
+fn is_service(kind: &UnitType) -> bool {
+    *kind == UnitType::Service
+}
+
+/// Reads one spec or a list of them, as JSON for `.json` files and YAML otherwise.
+pub fn parse_specs(path: &Path, text: &str) -> Result<Vec<UnitSpec>, String> {
+    let is_json = path.extension().is_some_and(|ext| ext == "json");
+    let value: serde_yaml::Value = if is_json {
+        serde_json::from_str(text).map_err(|e| e.to_string())?
+    } else {
+        serde_yaml::from_str(text).map_err(|e| e.to_string())?
+    };
+    if value.is_sequence() {
+        serde_yaml::from_value(value).map_err(|e| e.to_string())
+    } else {
+        serde_yaml::from_value(value)
+            .map(|spec| vec![spec])
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Value for Environment=, quoted when it would otherwise be split.
+fn environment_value(key: &str, value: &str) -> String {
+    if value.contains(char::is_whitespace) || value.contains('"') {
+        format!(
+            "\"{key}={}\"",
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    } else {
+        format!("{key}={value}")
+    }
+}
+
+/// Splits an Environment= value into its assignments, undoing the quoting.
+/// `None` when it cannot be read back unambiguously.
+fn parse_environment(value: &str) -> Option<Vec<(String, String)>> {
+    let mut assignments = vec![];
+    let mut current = String::new();
+    let mut chars = value.chars();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => current.push(chars.next()?),
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    assignments.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    if !current.is_empty() {
+        assignments.push(current);
+    }
+    assignments
+        .into_iter()
+        .map(|a| {
+            let (key, value) = a.split_once('=')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn push_extra(section: &mut Section, lines: Option<&Vec<String>>) -> Result<(), String> {
+    for line in lines.into_iter().flatten() {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("expected Key=Value in extra, found {line:?}"))?;
+        section.push(key.trim(), value.trim_start());
+    }
+    Ok(())
+}
+
+impl UnitSpec {
+    pub fn file_name(&self) -> String {
+        self.kind.file_name(&self.name)
+    }
+
+    /// Generates the unit and its timers as (file name, contents) pairs.
+    /// `mixins` are the available mixins, see `mixin::find_mixins`.
+    pub fn to_units(&self, mixins: &[(String, String)]) -> Result<Vec<(String, String)>, String> {
+        if self.name.is_empty() {
+            return Err("a spec needs a name".to_string());
+        }
+        let service_only = [
+            ("type", self.service_type.is_some()),
+            ("exec", self.exec.is_some()),
+            ("user", self.user.is_some()),
+            ("group", self.group.is_some()),
+            ("working_directory", self.working_directory.is_some()),
+            ("environment", !self.environment.is_empty()),
+            ("restart", self.restart.is_some()),
+            ("restart_sec", self.restart_sec.is_some()),
+            ("hardening", self.hardening.is_some()),
+            ("timers", !self.timers.is_empty()),
+        ];
+        if self.kind != UnitType::Service {
+            if let Some((field, _)) = service_only.iter().find(|(_, set)| *set) {
+                return Err(format!("{}: {field} only applies to services", self.name));
+            }
+        }
+
+        let mut unit = UnitFile::default();
+        let mut section = Section::new("Unit");
+        if let Some(description) = &self.description {
+            section.push("Description", description);
+        }
+        for (key, values) in self.dependencies.clone().fields() {
+            if !values.is_empty() {
+                section.push(key, &values.join(" "));
+            }
+        }
+        push_extra(&mut section, self.extra.get("Unit"))?;
+        if !section.lines.is_empty() {
+            *unit.section_or_insert("Unit") = section;
+        }
+
+        if let Some(name) = self.kind.type_section() {
+            let section = unit.section_or_insert(name);
+            let scalars = [
+                ("Type", &self.service_type),
+                ("ExecStart", &self.exec),
+                ("User", &self.user),
+                ("Group", &self.group),
+                ("WorkingDirectory", &self.working_directory),
+            ];
+            for (key, value) in scalars {
+                if let Some(value) = value {
+                    section.push(key, value);
+                }
+            }
+            for (key, value) in &self.environment {
+                section.push("Environment", &environment_value(key, value));
+            }
+            for (key, value) in [
+                ("Restart", &self.restart),
+                ("RestartSec", &self.restart_sec),
+            ] {
+                if let Some(value) = value {
+                    section.push(key, value);
+                }
+            }
+            push_extra(section, self.extra.get(name))?;
+        }
+
+        for (name, lines) in &self.extra {
+            if name != "Unit"
+                && name != "Install"
+                && Some(name.as_str()) != self.kind.type_section()
+            {
+                push_extra(unit.section_or_insert(name), Some(lines))?;
+            }
+        }
+
+        if let Some(profile) = &self.hardening {
+            let (_, contents) = mixins
+                .iter()
+                .find(|(name, _)| name == profile)
+                .ok_or_else(|| format!("{}: no hardening profile named {profile}", self.name))?;
+            let profile = UnitFile::parse(contents).map_err(|e| format!("{profile}: {e}"))?;
+            mixin::apply_mixin(&mut unit, &profile);
+        }
+
+        if !self.wanted_by.is_empty() || self.extra.contains_key("Install") {
+            let section = unit.section_or_insert("Install");
+            if !self.wanted_by.is_empty() {
+                section.push("WantedBy", &self.wanted_by.join(" "));
+            }
+            push_extra(section, self.extra.get("Install"))?;
+        }
+
+        let mut units = vec![(self.file_name(), unit.to_string())];
+        for timer in &self.timers {
+            units.push(self.timer_unit(timer));
+        }
+        Ok(units)
+    }
+
+    fn timer_unit(&self, timer: &TimerSpec) -> (String, String) {
+        let name = timer.name.as_deref().unwrap_or(&self.name);
+        let file_name = UnitType::Timer.file_name(name);
+
+        let mut unit = UnitFile::default();
+        if let Some(description) = &timer.description {
+            unit.section_or_insert("Unit")
+                .push("Description", description);
+        }
+        let section = unit.section_or_insert("Timer");
+        for calendar in &timer.on_calendar {
+            section.push("OnCalendar", calendar);
+        }
+        let scalars = [
+            ("OnBootSec", &timer.on_boot_sec),
+            ("OnUnitActiveSec", &timer.on_unit_active_sec),
+            ("RandomizedDelaySec", &timer.randomized_delay_sec),
+        ];
+        for (key, value) in scalars {
+            if let Some(value) = value {
+                section.push(key, value);
+            }
+        }
+        if let Some(persistent) = timer.persistent {
+            section.push("Persistent", if persistent { "yes" } else { "no" });
+        }
+        if timer.name.is_some() {
+            section.push("Unit", &self.file_name());
+        }
+        unit.section_or_insert("Install")
+            .push("WantedBy", "timers.target");
+
+        (file_name, unit.to_string())
+    }
+
+    /// Describes a parsed unit as a spec. Comments are dropped; directives
+    /// without a field of their own end up in `extra`. An empty assignment
+    /// resetting a list field is applied here, so only what follows it is kept.
+    pub fn from_unit(file_name: &str, unit: &UnitFile, mixins: &[(String, String)]) -> Self {
+        let kind = UnitType::from_file_name(file_name).unwrap_or_default();
+        let name = file_name
+            .strip_suffix(&format!(".{}", kind.suffix()))
+            .unwrap_or(file_name)
+            .to_string();
+        let mut spec = UnitSpec {
+            name,
+            kind,
+            ..Default::default()
+        };
+
+        let mut unit = unit.clone();
+        if kind == UnitType::Service {
+            spec.hardening = take_hardening(&mut unit, mixins);
+        }
+
+        for section in &unit.sections {
+            let mut rest = vec![];
+            let directives: Vec<&Directive> = section.directives().collect();
+            let single = |key: &str| {
+                let values = section.values(key);
+                (values.len() == 1).then(|| values[0].to_string())
+            };
+
+            for directive in &directives {
+                let key = directive.key.as_str();
+                let taken = match (section.name.as_str(), key) {
+                    ("Unit", "Description") => take(&mut spec.description, single(key)),
+                    ("Unit", "After" | "Before" | "Wants" | "Requires") => {
+                        let mut dependencies = std::mem::take(&mut spec.dependencies);
+                        for (name, values) in dependencies.fields() {
+                            if name == key {
+                                reset_or_extend(values, &directive.value);
+                            }
+                        }
+                        spec.dependencies = dependencies;
+                        true
+                    }
+                    ("Service", "Type") if kind == UnitType::Service => {
+                        take(&mut spec.service_type, single(key))
+                    }
+                    ("Service", "ExecStart") if kind == UnitType::Service => {
+                        take(&mut spec.exec, single(key))
+                    }
+                    ("Service", "User") if kind == UnitType::Service => {
+                        take(&mut spec.user, single(key))
+                    }
+                    ("Service", "Group") if kind == UnitType::Service => {
+                        take(&mut spec.group, single(key))
+                    }
+                    ("Service", "WorkingDirectory") if kind == UnitType::Service => {
+                        take(&mut spec.working_directory, single(key))
+                    }
+                    ("Service", "Restart") if kind == UnitType::Service => {
+                        take(&mut spec.restart, single(key))
+                    }
+                    ("Service", "RestartSec") if kind == UnitType::Service => {
+                        take(&mut spec.restart_sec, single(key))
+                    }
+                    ("Service", "Environment")
+                        if kind == UnitType::Service && directive.value.is_empty() =>
+                    {
+                        // Also drops values that could not be read back.
+                        spec.environment.clear();
+                        rest.retain(|line: &String| !line.starts_with("Environment="));
+                        if let Some(extra) = spec.extra.get_mut("Service") {
+                            extra.retain(|line| !line.starts_with("Environment="));
+                        }
+                        true
+                    }
+                    ("Service", "Environment") if kind == UnitType::Service => {
+                        match parse_environment(&directive.value) {
+                            Some(assignments) if !assignments.is_empty() => {
+                                spec.environment.extend(assignments);
+                                true
+                            }
+                            _ => false,
+                        }
+                    }
+                    ("Install", "WantedBy") => {
+                        reset_or_extend(&mut spec.wanted_by, &directive.value);
+                        true
+                    }
+                    _ => false,
+                };
+                if !taken {
+                    rest.push(format!("{}={}", directive.key, directive.value));
+                }
+            }
+
+            if !rest.is_empty() {
+                spec.extra
+                    .entry(section.name.clone())
+                    .or_default()
+                    .extend(rest);
+            }
+        }
+        spec
+    }
+
+    /// Attaches `timer` to this service if the timer activates it and every
+    /// setting it has fits `TimerSpec`. Returns false otherwise.
+    pub fn attach_timer(&mut self, file_name: &str, timer: &UnitFile) -> bool {
+        let Some(timer_name) = file_name.strip_suffix(".timer") else {
+            return false;
+        };
+        if self.kind != UnitType::Service {
+            return false;
+        }
+
+        let mut spec = TimerSpec::default();
+        let mut activates = None;
+        for section in &timer.sections {
+            for directive in section.directives() {
+                let value = directive.value.clone();
+                let fits = match (section.name.as_str(), directive.key.as_str()) {
+                    ("Unit", "Description") => take(&mut spec.description, Some(value)),
+                    ("Timer", "OnCalendar") if !value.is_empty() => {
+                        spec.on_calendar.push(value);
+                        true
+                    }
+                    ("Timer", "OnBootSec") => take(&mut spec.on_boot_sec, Some(value)),
+                    ("Timer", "OnUnitActiveSec") => take(&mut spec.on_unit_active_sec, Some(value)),
+                    ("Timer", "RandomizedDelaySec") => {
+                        take(&mut spec.randomized_delay_sec, Some(value))
+                    }
+                    ("Timer", "Persistent") => match value.as_str() {
+                        "yes" | "true" | "on" | "1" => take(&mut spec.persistent, Some(true)),
+                        "no" | "false" | "off" | "0" => take(&mut spec.persistent, Some(false)),
+                        _ => false,
+                    },
+                    ("Timer", "Unit") => take(&mut activates, Some(value)),
+                    ("Install", "WantedBy") => value == "timers.target",
+                    _ => false,
+                };
+                if !fits {
+                    return false;
+                }
+            }
+        }
+
+        let service = self.file_name();
+        match activates {
+            Some(unit) if unit == service => spec.name = Some(timer_name.to_string()),
+            Some(_) => return false,
+            None if timer_name == self.name => (),
+            None => return false,
+        }
+        self.timers.push(spec);
+        true
+    }
+}
+
+/// Adds the names in a list directive's `value` to `values`, or empties
+/// `values` for the empty assignment that resets the list.
+fn reset_or_extend(values: &mut Vec<String>, value: &str) {
+    if value.is_empty() {
+        values.clear();
+    } else {
+        values.extend(value.split_whitespace().map(str::to_string));
+    }
+}
+
+/// Sets `field` to `value` unless it is already set or there is no value.
+fn take<T>(field: &mut Option<T>, value: Option<T>) -> bool {
+    if field.is_some() || value.is_none() {
+        return false;
+    }
+    *field = value;
+    true
+}
+
+/// Finds a `sandboxing-*` mixin whose every directive the unit sets, and
+/// removes those directives from the unit.
+fn take_hardening(unit: &mut UnitFile, mixins: &[(String, String)]) -> Option<String> {
+    for (name, contents) in mixins {
+        if !name.starts_with("sandboxing-") {
+            continue;
+        }
+        let Ok(profile) = UnitFile::parse(contents) else {
+            continue;
+        };
+        let directives: Vec<(&str, &Directive)> = profile
+            .sections
+            .iter()
+            .flat_map(|s| s.directives().map(move |d| (s.name.as_str(), d)))
+            .collect();
+        let applied = !directives.is_empty()
+            && directives.iter().all(|(section, d)| {
+                unit.sections
+                    .iter()
+                    .find(|s| s.name == *section)
+                    .is_some_and(|s| s.values(&d.key) == [d.value.as_str()])
+            });
+        if !applied {
+            continue;
+        }
+
+        for (section, d) in directives {
+            if let Some(s) = unit.sections.iter_mut().find(|s| s.name == section) {
+                s.lines
+                    .retain(|line| !matches!(line, Line::Directive(x) if x.key == d.key));
+            }
+        }
+        return Some(name.clone());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) -> UnitFile {
+        let unit = UnitFile::parse(text).unwrap();
+        let spec = UnitSpec::from_unit("foo.service", &unit, &[]);
+        let units = spec.to_units(&[]).unwrap();
+        assert_eq!(units.len(), 1);
+        UnitFile::parse(&units[0].1).unwrap()
+    }
+
+    fn values<'a>(unit: &'a UnitFile, section: &str, key: &str) -> Vec<&'a str> {
+        unit.sections
+            .iter()
+            .filter(|s| s.name == section)
+            .flat_map(|s| s.values(key))
+            .collect()
+    }
+
+    #[test]
+    fn resets_keep_only_what_follows() {
+        let unit = round_trip(
+            "\
+[Unit]
+After=a.target
+After=
+After=b.target
+Wants=c.target
+Wants=
+
+[Service]
+ExecStart=/usr/bin/foo
+Environment=A=1
+Environment=\"B=two words\" C=3
+Environment=
+Environment=D=4
+
+[Install]
+WantedBy=default.target
+WantedBy=
+WantedBy=multi-user.target
+",
+        );
+        assert_eq!(values(&unit, "Unit", "After"), ["b.target"]);
+        assert!(values(&unit, "Unit", "Wants").is_empty());
+        assert_eq!(values(&unit, "Service", "Environment"), ["D=4"]);
+        assert_eq!(values(&unit, "Install", "WantedBy"), ["multi-user.target"]);
+    }
+
+    #[test]
+    fn reset_drops_environment_that_went_to_extra() {
+        let unit = round_trip(
+            "\
+[Service]
+ExecStart=/usr/bin/foo
+Environment=\"UNTERMINATED
+Environment=
+Environment=A=1
+",
+        );
+        assert_eq!(values(&unit, "Service", "Environment"), ["A=1"]);
+    }
+
+    #[test]
+    fn export_then_generate_is_stable() {
+        let text = "\
+[Unit]
+Description=Foo
+After=network.target
+
+[Service]
+Type=simple
+ExecStart=/usr/bin/foo --serve
+User=foo
+Environment=A=1 \"B=two words\"
+Restart=on-failure
+RestartSec=5
+LimitNOFILE=4096
+
+[Install]
+WantedBy=multi-user.target
+";
+        let once = round_trip(text);
+        let twice = round_trip(&once.to_string());
+        assert_eq!(once.to_string(), twice.to_string());
+        assert_eq!(values(&once, "Service", "LimitNOFILE"), ["4096"]);
+        assert_eq!(
+            values(&once, "Service", "Environment"),
+            ["A=1", "\"B=two words\""]
+        );
+    }
+}
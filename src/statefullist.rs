@@ -22,8 +22,8 @@ impl<T> StatefulList<T> {
         Self::new(items, ListState::default())
     }
 
-    /// Returns the selected item.
     /*
+    /// Returns the selected item.
     fn selected(&self) -> Option<&(T, T)> {
         self.items.get(self.state.selected()?)
     }
@@ -31,6 +31,9 @@ impl<T> StatefulList<T> {
 
     /// Selects the next item.
     pub fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -46,6 +49,9 @@ impl<T> StatefulList<T> {
 
     /// Selects the previous item.
     pub fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
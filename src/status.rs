@@ -0,0 +1,187 @@
+use std::{collections::HashMap, io, process::Command};
+
+use crate::scope::Scope;
+
+/// The parts of `systemctl status` shown on the status screen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnitStatus {
+    pub active_state: String,
+    pub sub_state: String,
+    pub main_pid: Option<u32>,
+    /// Current memory use in bytes.
+    pub memory: Option<u64>,
+    /// How the main process last ended, e.g. "exited, status=0".
+    pub last_exit: Option<String>,
+}
+
+/// Source of unit status and journal lines, replaceable with fixture data.
+pub trait StatusProvider {
+    fn status(&mut self, scope: Scope, unit: &str) -> io::Result<UnitStatus>;
+    /// The last `lines` journal entries of the unit, oldest first.
+    fn journal(&mut self, scope: Scope, unit: &str, lines: usize) -> io::Result<Vec<String>>;
+}
+
+/// `StatusProvider` backed by `systemctl show` and `journalctl`.
+#[derive(Default)]
+pub struct SystemctlStatus;
+
+const SHOW_PROPERTIES: &str =
+    "ActiveState,SubState,MainPID,MemoryCurrent,ExecMainCode,ExecMainStatus";
+
+fn run(mut command: Command) -> io::Result<String> {
+    let output = command.output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+/// Reads `Key=Value` lines as printed by `systemctl show`.
+fn parse_show(output: &str) -> UnitStatus {
+    let properties: HashMap<&str, &str> = output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .collect();
+    let get = |key: &str| properties.get(key).copied().unwrap_or_default();
+
+    // MainPID is 0 and MemoryCurrent "[not set]" when there is nothing to report.
+    let main_pid = get("MainPID").parse().ok().filter(|pid| *pid != 0);
+    let memory = get("MemoryCurrent").parse().ok();
+    let last_exit = match (get("ExecMainCode"), get("ExecMainStatus")) {
+        ("0", _) | ("", _) => None,
+        (code, status) => {
+            // Values of si_code from waitid(2).
+            let how = match code {
+                "1" => "exited",
+                "2" => "killed",
+                "3" => "dumped core",
+                _ => "ended",
+            };
+            Some(format!("{how}, status={status}"))
+        }
+    };
+
+    UnitStatus {
+        active_state: get("ActiveState").to_string(),
+        sub_state: get("SubState").to_string(),
+        main_pid,
+        memory,
+        last_exit,
+    }
+}
+
+impl StatusProvider for SystemctlStatus {
+    fn status(&mut self, scope: Scope, unit: &str) -> io::Result<UnitStatus> {
+        let mut command = Command::new("systemctl");
+        if scope != Scope::System {
+            command.arg("--user");
+        }
+        command.args(["show", "-p", SHOW_PROPERTIES, unit]);
+        Ok(parse_show(&run(command)?))
+    }
+
+    fn journal(&mut self, scope: Scope, unit: &str, lines: usize) -> io::Result<Vec<String>> {
+        let mut command = Command::new("journalctl");
+        if scope == Scope::System {
+            command.args(["--unit", unit]);
+        } else {
+            command.args(["--user-unit", unit]);
+        }
+        command.args(["--lines", &lines.to_string(), "--no-pager", "--quiet"]);
+        Ok(run(command)?.lines().map(str::to_string).collect())
+    }
+}
+
+/// Memory size the way systemctl prints it, e.g. "1.5M".
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}B")
+    } else {
+        format!("{value:.1}{}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_show_running_service() {
+        // systemctl show -p ActiveState,SubState,MainPID,MemoryCurrent,ExecMainCode,ExecMainStatus nginx.service
+        let output = "\
+MainPID=1123
+ExecMainCode=0
+ExecMainStatus=0
+MemoryCurrent=8151040
+ActiveState=active
+SubState=running
+";
+        assert_eq!(
+            parse_show(output),
+            UnitStatus {
+                active_state: "active".to_string(),
+                sub_state: "running".to_string(),
+                main_pid: Some(1123),
+                memory: Some(8151040),
+                last_exit: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_show_failed_service() {
+        let output = "\
+MainPID=0
+ExecMainCode=1
+ExecMainStatus=203
+MemoryCurrent=[not set]
+ActiveState=failed
+SubState=failed
+";
+        assert_eq!(
+            parse_show(output),
+            UnitStatus {
+                active_state: "failed".to_string(),
+                sub_state: "failed".to_string(),
+                main_pid: None,
+                memory: None,
+                last_exit: Some("exited, status=203".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_show_killed_service() {
+        let output = "\
+MainPID=0
+ExecMainCode=2
+ExecMainStatus=9
+MemoryCurrent=[not set]
+ActiveState=inactive
+SubState=dead
+";
+        let status = parse_show(output);
+        assert_eq!(status.last_exit.as_deref(), Some("killed, status=9"));
+        assert_eq!(status.sub_state, "dead");
+    }
+
+    #[test]
+    fn format_bytes_like_systemctl() {
+        assert_eq!(format_bytes(0), "0B");
+        assert_eq!(format_bytes(1023), "1023B");
+        assert_eq!(format_bytes(1536), "1.5K");
+        assert_eq!(format_bytes(8151040), "7.8M");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0G");
+        assert_eq!(format_bytes(5 << 50), "5120.0T");
+    }
+}
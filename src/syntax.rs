@@ -1,6 +1,10 @@
+use std::{path::Path, sync::OnceLock};
+
 use syntect::{
     easy::HighlightLines,
-    highlighting::{FontStyle, HighlightIterator, HighlightState, Highlighter, Style, ThemeSet},
+    highlighting::{
+        FontStyle, HighlightIterator, HighlightState, Highlighter, Style, Theme, ThemeSet,
+    },
     parsing::{ParseState, SyntaxSet, SyntaxSetBuilder},
     util::{self, LinesWithEndings},
     Error,
@@ -10,6 +14,17 @@ use tui::{
     widgets::{List, ListItem},
 };
 
+/// Theme for all highlighting, monokai unless `set_theme` picked another.
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Highlights with the .tmTheme file at `path` from now on.
+pub fn set_theme(path: &Path) -> Result<(), String> {
+    let theme = ThemeSet::get_theme(path)
+        .map_err(|e| format!("unable to load theme {}: {e}", path.display()))?;
+    let _ = THEME.set(theme);
+    Ok(())
+}
+
 /*
 struct SyntaxLine {
     items: Vec<(Style, usize, Range<usize>)>,
@@ -44,20 +59,23 @@ impl<'a> SyntaxText<'a> {
         //let theme = ThemeSet::get_theme("src/resources/monokai/monokai.tmTheme").unwrap();
         //let theme_set: ThemeSet = ThemeSet::load_defaults();
 
-        let theme_set = ThemeSet::load_from_folder("src/resources/monokai").unwrap();
+        let theme = THEME.get_or_init(|| {
+            let theme_set = ThemeSet::load_from_folder("src/resources/monokai").unwrap();
+            theme_set.themes["monokai"].clone()
+        });
 
         //let highlighter = Highlighter::new(&theme_set.themes["Solarized (dark)"]);
         //let highlighter = Highlighter::new(&theme_set.themes["monokai"]);
         //let mut highlight_state = HighlightState::new(&highlighter, ScopeStack::new());
 
         let syntax = syntax_set.find_syntax_by_extension("ini").unwrap();
-        let mut h = HighlightLines::new(syntax, &theme_set.themes["monokai"]);
+        let mut h = HighlightLines::new(syntax, theme);
 
         let syntax_lines: &mut Vec<SyntaxLine<'a>> = &mut Vec::new();
 
         //let mut parse_state = ParseState::new(syntax);
 
-        for line in LinesWithEndings::from(&text) {
+        for line in LinesWithEndings::from(text) {
             let ranges: Vec<(Style, &str)> = h.highlight_line(line, &syntax_set).unwrap();
             syntax_lines.push(SyntaxLine { items: ranges });
             /*
@@ -89,7 +107,7 @@ impl<'a> SyntaxText<'a> {
         let ops = parse_state.parse_line(line, syntax_set)?;
         use util::debug_print_ops;
         debug_print_ops(line, &ops);
-        let iter = HighlightIterator::new(highlight_state, &ops[..], line, &highlighter);
+        let iter = HighlightIterator::new(highlight_state, &ops[..], line, highlighter);
         Ok(iter.collect())
     }
 }
@@ -0,0 +1,328 @@
+use std::{io, process::Command};
+
+use serde::Deserialize;
+
+use crate::{escalate::Escalator, install, scope::Scope};
+
+/// Operations on units once they have been written.
+///
+/// The TUI only talks to this trait, so the backend can be swapped, e.g. for
+/// one that records the calls instead of touching the running system.
+pub trait UnitManager {
+    fn daemon_reload(&mut self, scope: Scope) -> io::Result<()>;
+    /// Enables the unit, and starts it too when `now` is set.
+    fn enable(&mut self, scope: Scope, unit: &str, now: bool) -> io::Result<()>;
+    fn disable(&mut self, scope: Scope, unit: &str) -> io::Result<()>;
+    fn start(&mut self, scope: Scope, unit: &str) -> io::Result<()>;
+    fn stop(&mut self, scope: Scope, unit: &str) -> io::Result<()>;
+    fn restart(&mut self, scope: Scope, unit: &str) -> io::Result<()>;
+    fn unit_state(&mut self, scope: Scope, unit: &str) -> io::Result<UnitState>;
+}
+
+/// ActiveState and SubState of a loaded unit, e.g. "active" and "running".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitState {
+    pub active_state: String,
+    pub sub_state: String,
+}
+
+/// The D-Bus backend when built with the `dbus` feature, systemctl otherwise.
+/// Without a running systemd, the [Install] symlinks are managed directly.
+///
+/// systemctl runs through `escalator` for the system and global scopes. Over
+/// D-Bus, polkit decides instead.
+pub fn default_manager(escalator: Option<Box<dyn Escalator>>) -> Box<dyn UnitManager> {
+    if !install::systemd_running() {
+        return Box::new(install::OfflineManager {
+            root: None,
+            escalator,
+        });
+    }
+
+    #[cfg(feature = "dbus")]
+    {
+        drop(escalator);
+        Box::new(crate::dbus::DbusManager::default())
+    }
+
+    #[cfg(not(feature = "dbus"))]
+    Box::new(SystemctlCli { escalator })
+}
+
+/// Actions offered after a unit has been saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PostSaveAction {
+    DaemonReload,
+    Enable,
+    EnableNow,
+    Disable,
+    Start,
+    Restart,
+}
+
+impl PostSaveAction {
+    pub const ALL: [PostSaveAction; 6] = [
+        PostSaveAction::DaemonReload,
+        PostSaveAction::Enable,
+        PostSaveAction::EnableNow,
+        PostSaveAction::Disable,
+        PostSaveAction::Start,
+        PostSaveAction::Restart,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PostSaveAction::DaemonReload => "daemon-reload",
+            PostSaveAction::Enable => "enable",
+            PostSaveAction::EnableNow => "enable --now",
+            PostSaveAction::Disable => "disable",
+            PostSaveAction::Start => "start",
+            PostSaveAction::Restart => "restart",
+        }
+    }
+
+    /// Whether the action changes what is running, so the resulting state is worth showing.
+    pub fn affects_state(&self) -> bool {
+        matches!(
+            self,
+            PostSaveAction::EnableNow | PostSaveAction::Start | PostSaveAction::Restart
+        )
+    }
+
+    /// Whether the action works without a running systemd, as under --root.
+    pub fn works_offline(&self) -> bool {
+        matches!(
+            self,
+            PostSaveAction::DaemonReload | PostSaveAction::Enable | PostSaveAction::Disable
+        )
+    }
+
+    pub fn run(&self, manager: &mut dyn UnitManager, scope: Scope, unit: &str) -> io::Result<()> {
+        match self {
+            PostSaveAction::DaemonReload => manager.daemon_reload(scope),
+            PostSaveAction::Enable => manager.enable(scope, unit, false),
+            PostSaveAction::EnableNow => manager.enable(scope, unit, true),
+            PostSaveAction::Disable => manager.disable(scope, unit),
+            PostSaveAction::Start => manager.start(scope, unit),
+            PostSaveAction::Restart => manager.restart(scope, unit),
+        }
+    }
+}
+
+/// `UnitManager` that shells out to systemctl.
+#[derive(Default)]
+#[cfg_attr(feature = "dbus", allow(dead_code))]
+pub struct SystemctlCli {
+    /// Runs systemctl as root for the system and global scopes, when the app
+    /// itself is not root.
+    escalator: Option<Box<dyn Escalator>>,
+}
+
+#[cfg_attr(feature = "dbus", allow(dead_code))]
+impl SystemctlCli {
+    /// Runs a systemctl command that changes something, as root if needed.
+    fn systemctl(&mut self, scope: Scope, args: &[&str]) -> io::Result<String> {
+        match &mut self.escalator {
+            Some(escalator) if scope != Scope::User => {
+                escalator.run("systemctl", &scope_args(scope, args), None)
+            }
+            _ => self.query(scope, args),
+        }
+    }
+
+    /// Runs a systemctl command that only reads, which never needs root.
+    fn query(&self, scope: Scope, args: &[&str]) -> io::Result<String> {
+        let args = scope_args(scope, args);
+        let output = Command::new("systemctl").args(&args).output()?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(io::Error::other(format!(
+                "systemctl {} failed: {}",
+                args.join(" "),
+                stderr.trim()
+            )))
+        }
+    }
+
+    /// The global user scope only has unit files, there is no manager to talk to.
+    fn running_manager(&self, scope: Scope) -> io::Result<()> {
+        if scope == Scope::GlobalUser {
+            return Err(io::Error::other(
+                "the global user scope has no running manager, log in as a user instead",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `args` preceded by the systemctl flag selecting `scope`.
+#[cfg_attr(feature = "dbus", allow(dead_code))]
+fn scope_args<'a>(scope: Scope, args: &[&'a str]) -> Vec<&'a str> {
+    let flag = match scope {
+        Scope::System => None,
+        Scope::User => Some("--user"),
+        Scope::GlobalUser => Some("--global"),
+    };
+    flag.into_iter().chain(args.iter().copied()).collect()
+}
+
+impl UnitManager for SystemctlCli {
+    fn daemon_reload(&mut self, scope: Scope) -> io::Result<()> {
+        self.running_manager(scope)?;
+        self.systemctl(scope, &["daemon-reload"])?;
+        Ok(())
+    }
+
+    fn enable(&mut self, scope: Scope, unit: &str, now: bool) -> io::Result<()> {
+        if now {
+            self.running_manager(scope)?;
+            self.systemctl(scope, &["enable", "--now", unit])?;
+        } else {
+            self.systemctl(scope, &["enable", unit])?;
+        }
+        Ok(())
+    }
+
+    fn disable(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.systemctl(scope, &["disable", unit])?;
+        Ok(())
+    }
+
+    fn start(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.running_manager(scope)?;
+        self.systemctl(scope, &["start", unit])?;
+        Ok(())
+    }
+
+    fn stop(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.running_manager(scope)?;
+        self.systemctl(scope, &["stop", unit])?;
+        Ok(())
+    }
+
+    fn restart(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.running_manager(scope)?;
+        self.systemctl(scope, &["restart", unit])?;
+        Ok(())
+    }
+
+    fn unit_state(&mut self, scope: Scope, unit: &str) -> io::Result<UnitState> {
+        self.running_manager(scope)?;
+        let output = self.query(
+            scope,
+            &["show", "-p", "ActiveState", "-p", "SubState", unit],
+        )?;
+
+        let property = |name: &str| {
+            output
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+                .unwrap_or_default()
+                .to_string()
+        };
+        Ok(UnitState {
+            active_state: property("ActiveState"),
+            sub_state: property("SubState"),
+        })
+    }
+}
+
+/// `UnitManager` that records the calls it gets instead of running anything.
+/// Clones share the record, so a test can keep one and hand the other out.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingManager {
+    pub calls: std::rc::Rc<std::cell::RefCell<Vec<(Scope, String)>>>,
+}
+
+#[cfg(test)]
+impl RecordingManager {
+    fn record(&mut self, scope: Scope, call: String) -> io::Result<()> {
+        self.calls.borrow_mut().push((scope, call));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl UnitManager for RecordingManager {
+    fn daemon_reload(&mut self, scope: Scope) -> io::Result<()> {
+        self.record(scope, "daemon-reload".to_string())
+    }
+
+    fn enable(&mut self, scope: Scope, unit: &str, now: bool) -> io::Result<()> {
+        if now {
+            self.record(scope, format!("enable --now {unit}"))
+        } else {
+            self.record(scope, format!("enable {unit}"))
+        }
+    }
+
+    fn disable(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.record(scope, format!("disable {unit}"))
+    }
+
+    fn start(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.record(scope, format!("start {unit}"))
+    }
+
+    fn stop(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.record(scope, format!("stop {unit}"))
+    }
+
+    fn restart(&mut self, scope: Scope, unit: &str) -> io::Result<()> {
+        self.record(scope, format!("restart {unit}"))
+    }
+
+    fn unit_state(&mut self, scope: Scope, unit: &str) -> io::Result<UnitState> {
+        self.record(scope, format!("unit-state {unit}"))?;
+        Ok(UnitState {
+            active_state: "active".to_string(),
+            sub_state: "running".to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_save_actions_call_the_manager() {
+        let expected = [
+            "daemon-reload",
+            "enable foo.service",
+            "enable --now foo.service",
+            "disable foo.service",
+            "start foo.service",
+            "restart foo.service",
+        ];
+        for (action, expected) in PostSaveAction::ALL.iter().zip(expected) {
+            for scope in Scope::ALL {
+                let mut manager = RecordingManager::default();
+                action.run(&mut manager, scope, "foo.service").unwrap();
+                assert_eq!(
+                    *manager.calls.borrow(),
+                    [(scope, expected.to_string())],
+                    "{}",
+                    action.label()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scope_args_select_the_manager() {
+        assert_eq!(scope_args(Scope::System, &["start", "a"]), ["start", "a"]);
+        assert_eq!(
+            scope_args(Scope::User, &["start", "a"]),
+            ["--user", "start", "a"]
+        );
+        assert_eq!(
+            scope_args(Scope::GlobalUser, &["enable", "a"]),
+            ["--global", "enable", "a"]
+        );
+    }
+}
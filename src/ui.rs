@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use tui::{
     backend::Backend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
@@ -8,9 +10,16 @@ use tui::{
 };
 
 use crate::{
-    app::{App, AppState},
+    app::{App, AppState, StatusMessage},
     command::MenuCommands,
+    diff::DiffLine,
+    ledger::EntryState,
+    scope::Scope,
+    status::format_bytes,
     syntax::SyntaxText,
+    systemctl::PostSaveAction,
+    unitpaths,
+    verify::Severity,
 };
 
 const TOP_SECTION: usize = 0;
@@ -69,7 +78,17 @@ pub fn ui<B: Backend>(frame: &mut Frame<B>, app: &mut App) {
     let title = match app.app_state {
         AppState::SelectServiceTemplate => "Select template".to_string(),
         AppState::ChooseServiceName => "Enter service name".to_string(),
-        _ => format!("{}.service", app.service_name),
+        AppState::ImportUnit => "Import installed unit".to_string(),
+        AppState::ManagedUnits => "Managed units".to_string(),
+        AppState::ConfirmRemoval => match &app.removal {
+            Some(removal) => format!("Remove {} ({})", removal.unit, removal.scope.name()),
+            None => "Remove unit".to_string(),
+        },
+        _ if app.dropin_mode => format!("{} (drop-in)", app.unit_file_name()),
+        _ => match &app.opened_path {
+            Some(path) => path.display().to_string(),
+            None => app.unit_file_name(),
+        },
     };
 
     let title_paragraph = Paragraph::new(title)
@@ -85,7 +104,7 @@ pub fn ui<B: Backend>(frame: &mut Frame<B>, app: &mut App) {
     frame.render_widget(title_paragraph, chunks[TOP_SECTION]);
 
     match app.app_state {
-        AppState::SelectServiceTemplate | AppState::ChooseServiceName => {
+        AppState::SelectServiceTemplate | AppState::SelectMixins | AppState::ChooseServiceName => {
             let templates_block = Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::White))
@@ -117,22 +136,52 @@ pub fn ui<B: Backend>(frame: &mut Frame<B>, app: &mut App) {
                 .get(app.lhs_list.state.selected().unwrap())
                 .unwrap();
 
-            let styled_contents = SyntaxText::new(&template_contents);
+            let styled_contents = SyntaxText::new(template_contents);
 
             let systemd_detail = List::from(styled_contents);
 
             let main_section_chunks = Layout::default()
                 .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
+                .constraints(
+                    [
+                        Constraint::Percentage(app.config.list_width),
+                        Constraint::Percentage(100 - app.config.list_width),
+                    ]
+                    .as_ref(),
+                )
                 .split(chunks[MAIN_SECTION]);
 
+            let lhs_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Min(3),
+                        Constraint::Length(diagnostics_height(&app.diagnostics)),
+                    ]
+                    .as_ref(),
+                )
+                .split(main_section_chunks[MAIN_LHS]);
+
             frame.render_widget(Clear, main_section_chunks[MAIN_RHS]);
             frame.render_widget(systemd_detail, main_section_chunks[MAIN_RHS]);
-            frame.render_stateful_widget(
-                list,
-                main_section_chunks[MAIN_LHS],
-                &mut app.lhs_list.state,
-            );
+            frame.render_stateful_widget(list, lhs_chunks[0], &mut app.lhs_list.state);
+
+            if !app.diagnostics.is_empty() {
+                let problems: Vec<_> = app
+                    .diagnostics
+                    .iter()
+                    .map(|d| ListItem::new(Span::raw(d.clone())))
+                    .collect();
+                let problems_list = List::new(problems)
+                    .style(Style::default().fg(Color::Yellow))
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .style(Style::default().fg(Color::Yellow))
+                            .title("Skipped templates"),
+                    );
+                frame.render_widget(problems_list, lhs_chunks[1]);
+            }
 
             let input = Paragraph::new(app.service_name.as_ref())
                 .style(Style::default().fg(Color::White))
@@ -148,32 +197,238 @@ pub fn ui<B: Backend>(frame: &mut Frame<B>, app: &mut App) {
                 frame.render_widget(Clear, area); //this clears out the background
                 frame.render_widget(input, area);
             }
+
+            if app.app_state == AppState::SelectMixins {
+                let items: Vec<_> = app
+                    .mixin_list
+                    .items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (name, _))| {
+                        let tick = if app.selected_mixins.contains(&index) {
+                            "[x] "
+                        } else {
+                            "[ ] "
+                        };
+                        ListItem::new(Span::raw(tick.to_string() + name))
+                    })
+                    .collect();
+
+                let mixins = List::new(items)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_style(Style::default().fg(Color::Yellow))
+                            .title("Apply mixins"),
+                    )
+                    .highlight_style(
+                        Style::default()
+                            .bg(Color::Yellow)
+                            .fg(Color::Black)
+                            .add_modifier(Modifier::BOLD),
+                    );
+
+                let area = centered_rect(40, 40, frame.size());
+                frame.render_widget(Clear, area);
+                frame.render_stateful_widget(mixins, area, &mut app.mixin_list.state);
+            }
         }
         AppState::EnteringEditMode => {
             app.initialise_edit();
         }
         AppState::ViewService => (),
         AppState::ModifyingService => {}
-    }
+        AppState::ChooseTemplateName => {}
+        AppState::ChooseScope => {}
+        AppState::RenameUnit => {}
+        AppState::PostSave => {}
+        AppState::UnitStatus => render_unit_status(frame, app, chunks[MAIN_SECTION]),
+        AppState::ConfirmOverwrite => {
+            let diff_block = Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(format!("{} already exists", app.unit_file_name()));
+            let diff_list = List::new(diff_list_items(&app.overwrite_diff)).block(diff_block);
+            frame.render_widget(diff_list, chunks[MAIN_SECTION]);
+        }
+        AppState::SaveOpenedUnit => {
+            let title = match &app.opened_path {
+                Some(path) => format!("Changes to {}", path.display()),
+                None => "Changes".to_string(),
+            };
+            let diff_block = Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title(title);
+            let diff_list = List::new(diff_list_items(&app.overwrite_diff)).block(diff_block);
+            frame.render_widget(diff_list, chunks[MAIN_SECTION]);
+        }
+        AppState::ManagedUnits => {
+            let units_block = Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Units written by this tool")
+                .border_type(BorderType::Plain);
+            let items: Vec<_> = app
+                .managed_units()
+                .into_iter()
+                .map(|(entry, state)| {
+                    let color = match state {
+                        EntryState::Unchanged => Color::White,
+                        EntryState::Modified => Color::Yellow,
+                        EntryState::Missing => Color::Red,
+                    };
+                    ListItem::new(Spans::from(vec![
+                        Span::raw(format!(
+                            "{:<32} {:<12} {}  ",
+                            entry.unit,
+                            entry.scope.name(),
+                            entry.written_at
+                        )),
+                        Span::styled(state.label(), Style::default().fg(color)),
+                    ]))
+                })
+                .collect();
+            let list = List::new(items).block(units_block).highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            );
+            frame.render_stateful_widget(list, chunks[MAIN_SECTION], &mut app.managed_list);
+        }
+        AppState::ConfirmRemoval => {
+            let plan_block = Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Press Enter to run these steps")
+                .border_type(BorderType::Plain);
+            let items: Vec<_> = app
+                .removal
+                .iter()
+                .flat_map(|removal| {
+                    removal.steps.iter().enumerate().map(|(i, step)| {
+                        ListItem::new(Span::raw(format!(
+                            "{}. {}",
+                            i + 1,
+                            step.describe(&removal.unit)
+                        )))
+                    })
+                })
+                .collect();
+            frame.render_widget(List::new(items).block(plan_block), chunks[MAIN_SECTION]);
+        }
+        AppState::ImportUnit => {
+            let units_block = Block::default()
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::White))
+                .title("Installed units")
+                .border_type(BorderType::Plain);
+
+            let items: Vec<_> = app
+                .import_list
+                .items
+                .iter()
+                .map(|(unit, path)| {
+                    match unitpaths::scope_of(app.root.as_deref(), Path::new(path)) {
+                        Some(scope) => {
+                            ListItem::new(Span::raw(format!("{unit} ({})", scope.name())))
+                        }
+                        None => ListItem::new(Span::raw(unit.clone())),
+                    }
+                })
+                .collect();
+
+            let list = List::new(items).block(units_block).highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            );
+
+            let main_section_chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(
+                    [
+                        Constraint::Percentage(app.config.list_width),
+                        Constraint::Percentage(100 - app.config.list_width),
+                    ]
+                    .as_ref(),
+                )
+                .split(chunks[MAIN_SECTION]);
 
-    if app.just_saved {
-        let status_message = "Saved in /etc/systemd/system.";
+            frame.render_widget(Clear, main_section_chunks[MAIN_RHS]);
+            if let Some((_, path, contents)) = app.selected_installed_unit() {
+                let styled_contents = SyntaxText::new(&contents);
+                let unit_detail = List::from(styled_contents).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .style(Style::default().fg(Color::White))
+                        .title(path.to_string()),
+                );
+                frame.render_widget(unit_detail, main_section_chunks[MAIN_RHS]);
+            }
+            frame.render_stateful_widget(
+                list,
+                main_section_chunks[MAIN_LHS],
+                &mut app.import_list.state,
+            );
+        }
+    }
 
+    if let Some(status_message) = app.status_message.take() {
+        let (status_message, background) = match status_message {
+            StatusMessage::Info(message) => (message, Color::White),
+            StatusMessage::Error(message) => (message, Color::Red),
+        };
         let status_paragraph = Paragraph::new(status_message)
-            .style(Style::default().fg(Color::Black).bg(Color::White))
+            .style(Style::default().fg(Color::Black).bg(background))
+            .alignment(Alignment::Center);
+
+        frame.render_widget(status_paragraph, chunks[STATUS_SECTION]);
+    } else if let (AppState::ViewService, Some(diagnostic)) =
+        (&app.app_state, app.selected_line_diagnostic())
+    {
+        let color = match diagnostic.severity {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+        };
+        let message = match diagnostic.rule {
+            Some(rule) => format!("{} [{rule}]", diagnostic.message),
+            None => diagnostic.message.clone(),
+        };
+        let status_paragraph = Paragraph::new(message)
+            .style(Style::default().fg(Color::Black).bg(color))
+            .alignment(Alignment::Center);
+
+        frame.render_widget(status_paragraph, chunks[STATUS_SECTION]);
+    } else if let (AppState::ViewService, Some(first)) = (
+        &app.app_state,
+        app.validation.first().cloned().or_else(|| {
+            app.lint_diagnostics
+                .first()
+                .map(|d| format!("{} [{}]", d.message, d.rule.unwrap_or_default()))
+        }),
+    ) {
+        let mut problem = first;
+        let more = app.validation.len() + app.lint_diagnostics.len() - 1;
+        if more > 0 {
+            problem += &format!(" (+{more} more)");
+        }
+        let status_paragraph = Paragraph::new(problem)
+            .style(Style::default().fg(Color::Black).bg(Color::Yellow))
             .alignment(Alignment::Center);
 
         frame.render_widget(status_paragraph, chunks[STATUS_SECTION]);
-        app.just_saved = false;
     }
 
-    let commands_map = MenuCommands::default();
+    let commands_map = MenuCommands::new(&app.config.keys);
     let commands = commands_map.commands.get(&app.app_state).unwrap();
 
-    let mut line_span = Spans(Vec::with_capacity(commands.iter().count()));
+    let mut line_span = Spans(Vec::with_capacity(commands.len()));
     for command in commands.iter() {
         line_span.0.push(Span::styled(
-            command.shortcut,
+            command.shortcut.clone(),
             Style::default()
                 .bg(Color::White)
                 .fg(Color::Black)
@@ -199,13 +454,34 @@ pub fn ui<B: Backend>(frame: &mut Frame<B>, app: &mut App) {
     frame.render_widget(commands_paragraph, chunks[BOTTOM_SECTION]);
 
     match app.app_state {
-        AppState::EnteringEditMode | AppState::ViewService | AppState::ModifyingService => {
+        AppState::EnteringEditMode
+        | AppState::ViewService
+        | AppState::ModifyingService
+        | AppState::ChooseTemplateName
+        | AppState::ChooseScope
+        | AppState::RenameUnit
+        | AppState::PostSave => {
             let s = &app.selected_template_contents();
             let syntax_text = SyntaxText::new(s);
             let items: Vec<Spans> = syntax_text.into();
             let content_list_items: Vec<ListItem> = items
                 .iter()
-                .map(|s| ListItem::new(Text::from(s.clone())))
+                .enumerate()
+                .map(|(index, s)| {
+                    let mut line = s.clone();
+                    let severity = app
+                        .diagnostics()
+                        .filter(|d| d.line == Some(index + 1))
+                        .map(|d| d.severity)
+                        .max_by_key(|severity| *severity == Severity::Error);
+                    for (i, span) in gutter(app.placeholders.contains(&index), severity)
+                        .into_iter()
+                        .enumerate()
+                    {
+                        line.0.insert(i, span);
+                    }
+                    ListItem::new(Text::from(line))
+                })
                 .collect();
 
             let systemd_detail = List::new(content_list_items).highlight_style(
@@ -237,11 +513,241 @@ pub fn ui<B: Backend>(frame: &mut Frame<B>, app: &mut App) {
                 frame.render_widget(Clear, area2); //this clears out the background
                 frame.render_widget(input2, area2);
             }
+
+            if app.app_state == AppState::ChooseTemplateName {
+                let input = Paragraph::new(app.template_name.as_ref())
+                    .style(Style::default().fg(Color::White))
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_style(Style::default().fg(Color::Yellow))
+                            .title("Name of Template"),
+                    );
+
+                let area = centered_rect(60, 15, frame.size());
+                frame.render_widget(Clear, area);
+                frame.render_widget(input, area);
+            }
+
+            if app.app_state == AppState::RenameUnit {
+                let input = Paragraph::new(app.service_name.as_ref())
+                    .style(Style::default().fg(Color::White))
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_style(Style::default().fg(Color::Yellow))
+                            .title("Save under another name"),
+                    );
+
+                let area = centered_rect(60, 15, frame.size());
+                frame.render_widget(Clear, area);
+                frame.render_widget(input, area);
+            }
+
+            if app.app_state == AppState::PostSave {
+                let flag = match app.scope {
+                    Scope::System => "",
+                    Scope::User => "--user ",
+                    Scope::GlobalUser => "--global ",
+                };
+                let unit_file_name = app.unit_file_name();
+                let items: Vec<_> = app
+                    .config
+                    .post_save_actions
+                    .iter()
+                    .map(|action| {
+                        let unit = match action {
+                            PostSaveAction::DaemonReload => "",
+                            _ => unit_file_name.as_str(),
+                        };
+                        ListItem::new(Span::raw(format!(
+                            "systemctl {flag}{} {unit}",
+                            action.label()
+                        )))
+                    })
+                    .collect();
+
+                let actions = List::new(items)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_style(Style::default().fg(Color::Yellow))
+                            .title("Saved. Run"),
+                    )
+                    .highlight_style(
+                        Style::default()
+                            .bg(Color::Yellow)
+                            .fg(Color::Black)
+                            .add_modifier(Modifier::BOLD),
+                    );
+
+                let area = centered_rect(60, 25, frame.size());
+                frame.render_widget(Clear, area);
+                frame.render_stateful_widget(actions, area, &mut app.post_save_list);
+            }
+
+            if app.app_state == AppState::ChooseScope {
+                let unit_file_name = app.unit_file_name();
+                let items: Vec<_> = Scope::ALL
+                    .iter()
+                    .map(|scope| {
+                        let dir = app
+                            .unit_dir(*scope)
+                            .map(|dir| dir.join(&unit_file_name).display().to_string())
+                            .unwrap_or_else(|| "unavailable".to_string());
+                        ListItem::new(Span::raw(format!("{:<12} {dir}", scope.name())))
+                    })
+                    .collect();
+
+                let scopes = List::new(items)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_style(Style::default().fg(Color::Yellow))
+                            .title("Install for"),
+                    )
+                    .highlight_style(
+                        Style::default()
+                            .bg(Color::Yellow)
+                            .fg(Color::Black)
+                            .add_modifier(Modifier::BOLD),
+                    );
+
+                let area = centered_rect(60, 20, frame.size());
+                frame.render_widget(Clear, area);
+                frame.render_stateful_widget(scopes, area, &mut app.scope_list);
+            }
         }
         _ => (),
     }
 }
 
+/// Summary in the style of `systemctl status` above the unit's recent journal.
+fn render_unit_status<B: Backend>(frame: &mut Frame<B>, app: &App, area: Rect) {
+    let status_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(6), Constraint::Min(3)].as_ref())
+        .split(area);
+
+    let not_set = || "-".to_string();
+    let summary = match &app.unit_status {
+        Some(status) => {
+            let colour = match status.active_state.as_str() {
+                "active" => Color::Green,
+                "failed" => Color::Red,
+                _ => Color::Yellow,
+            };
+            vec![
+                Spans::from(vec![
+                    Span::raw("Active:    "),
+                    Span::styled(
+                        format!("{} ({})", status.active_state, status.sub_state),
+                        Style::default().fg(colour).add_modifier(Modifier::BOLD),
+                    ),
+                ]),
+                Spans::from(format!(
+                    "Main PID:  {}",
+                    status.main_pid.map_or_else(not_set, |pid| pid.to_string())
+                )),
+                Spans::from(format!(
+                    "Memory:    {}",
+                    status.memory.map_or_else(not_set, format_bytes)
+                )),
+                Spans::from(format!(
+                    "Last exit: {}",
+                    status.last_exit.clone().unwrap_or_else(not_set)
+                )),
+            ]
+        }
+        None => vec![Spans::from("Status unavailable.")],
+    };
+
+    let summary_paragraph = Paragraph::new(summary).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title(format!("systemctl status {}", app.unit_file_name())),
+    );
+
+    let journal: Vec<_> = app
+        .journal
+        .iter()
+        .map(|line| ListItem::new(Span::raw(line.clone())))
+        .collect();
+    let journal_list = List::new(journal).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::White))
+            .title("Journal"),
+    );
+
+    frame.render_widget(summary_paragraph, status_chunks[0]);
+    frame.render_widget(journal_list, status_chunks[1]);
+}
+
+/// Colourised unified diff, with the line contents syntax highlighted like the editor.
+fn diff_list_items(diff: &[DiffLine]) -> Vec<ListItem<'static>> {
+    let text = diff
+        .iter()
+        .map(|line| line.text())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let highlighted: Vec<Spans> = SyntaxText::new(&text).into();
+
+    diff.iter()
+        .zip(highlighted)
+        .map(|(line, spans)| {
+            let (marker, colour) = match line {
+                DiffLine::Hunk(header) => {
+                    return ListItem::new(Span::styled(
+                        header.clone(),
+                        Style::default().fg(Color::Cyan),
+                    ));
+                }
+                DiffLine::Context(_) => (" ", Color::Reset),
+                DiffLine::Removed(_) => ("-", Color::Red),
+                DiffLine::Added(_) => ("+", Color::Green),
+            };
+            let marker = Span::styled(
+                marker.to_string() + " ",
+                Style::default().fg(colour).add_modifier(Modifier::BOLD),
+            );
+            let contents = spans
+                .0
+                .into_iter()
+                .map(|span| Span::styled(span.content.into_owned(), span.style));
+            ListItem::new(Text::from(Spans(
+                std::iter::once(marker).chain(contents).collect(),
+            )))
+        })
+        .collect()
+}
+
+/// Rows needed to list template diagnostics, capped so the templates stay visible.
+fn diagnostics_height(diagnostics: &[String]) -> u16 {
+    if diagnostics.is_empty() {
+        0
+    } else {
+        diagnostics.len().min(5) as u16 + 2
+    }
+}
+
+/// Markers shown in front of a line: `{}` when its value becomes a placeholder
+/// in a saved template, `!` when the verifier complained about it.
+fn gutter<'a>(is_placeholder: bool, severity: Option<Severity>) -> [Span<'a>; 2] {
+    let placeholder = if is_placeholder {
+        Span::styled("{}", Style::default().fg(Color::Yellow))
+    } else {
+        Span::raw("  ")
+    };
+    let diagnostic = match severity {
+        Some(Severity::Error) => Span::styled("!", Style::default().fg(Color::Red)),
+        Some(Severity::Warning) => Span::styled("!", Style::default().fg(Color::Yellow)),
+        None => Span::raw(" "),
+    };
+    [placeholder, diagnostic]
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -0,0 +1,372 @@
+use std::fmt;
+
+/// A `Key=Value` assignment inside a section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    pub key: String,
+    pub value: String,
+}
+
+/// One line of a unit file. Comments and blank lines are kept so a parsed unit
+/// renders back the way it was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Directive(Directive),
+    Comment(String),
+    Blank,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub lines: Vec<Line>,
+}
+
+/// Parsed representation of a systemd unit file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnitFile {
+    /// Comments and blank lines before the first section header.
+    pub preamble: Vec<Line>,
+    pub sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Directives that accumulate when assigned more than once. An empty
+/// assignment resets the list.
+const LIST_DIRECTIVES: &[&str] = &[
+    "After",
+    "AmbientCapabilities",
+    "Also",
+    "Alias",
+    "Before",
+    "BindPaths",
+    "BindReadOnlyPaths",
+    "BindsTo",
+    "CapabilityBoundingSet",
+    "Conflicts",
+    "DeviceAllow",
+    "Documentation",
+    "Environment",
+    "EnvironmentFile",
+    "ExecCondition",
+    "ExecReload",
+    "ExecStart",
+    "ExecStartPost",
+    "ExecStartPre",
+    "ExecStop",
+    "ExecStopPost",
+    "InaccessiblePaths",
+    "ListenDatagram",
+    "ListenFIFO",
+    "ListenSequentialPacket",
+    "ListenStream",
+    "OnActiveSec",
+    "OnBootSec",
+    "OnCalendar",
+    "OnFailure",
+    "OnStartupSec",
+    "OnUnitActiveSec",
+    "OnUnitInactiveSec",
+    "PartOf",
+    "PassEnvironment",
+    "PathChanged",
+    "PathExists",
+    "PathExistsGlob",
+    "PathModified",
+    "ReadOnlyPaths",
+    "ReadWritePaths",
+    "RequiredBy",
+    "Requires",
+    "Requisite",
+    "RestrictAddressFamilies",
+    "SupplementaryGroups",
+    "SystemCallFilter",
+    "WantedBy",
+    "Wants",
+];
+
+/// Whether `key` names a directive that may be assigned several times.
+pub fn is_list_directive(key: &str) -> bool {
+    LIST_DIRECTIVES.contains(&key) || key.starts_with("Condition") || key.starts_with("Assert")
+}
+
+impl Section {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            lines: vec![],
+        }
+    }
+
+    pub fn directives(&self) -> impl Iterator<Item = &Directive> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Directive(directive) => Some(directive),
+            _ => None,
+        })
+    }
+
+    /// All values assigned to `key`, in order.
+    pub fn values(&self, key: &str) -> Vec<&str> {
+        self.directives()
+            .filter(|d| d.key == key)
+            .map(|d| d.value.as_str())
+            .collect()
+    }
+
+    /// Replaces every assignment of `key` with a single one, keeping the position
+    /// of the first assignment.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.position(key) {
+            Some(index) => {
+                self.lines[index] = Line::Directive(Directive {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                });
+                let mut seen = 0;
+                self.lines.retain(|line| match line {
+                    Line::Directive(d) if d.key == key => {
+                        seen += 1;
+                        seen == 1
+                    }
+                    _ => true,
+                });
+            }
+            None => self.push(key, value),
+        }
+    }
+
+    /// Adds an assignment after the last directive of the section.
+    pub fn push(&mut self, key: &str, value: &str) {
+        let index = self
+            .lines
+            .iter()
+            .rposition(|line| matches!(line, Line::Directive(_)))
+            .map_or(0, |i| i + 1);
+        self.lines.insert(
+            index,
+            Line::Directive(Directive {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+        );
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.lines
+            .retain(|line| !matches!(line, Line::Directive(d) if d.key == key));
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.lines
+            .iter()
+            .position(|line| matches!(line, Line::Directive(d) if d.key == key))
+    }
+}
+
+impl UnitFile {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut unit = UnitFile::default();
+        let mut continued: Option<Directive> = None;
+
+        for (index, raw) in text.lines().enumerate() {
+            if let Some(mut directive) = continued.take() {
+                directive.value.push('\n');
+                directive.value.push_str(raw);
+                if raw.ends_with('\\') {
+                    continued = Some(directive);
+                } else {
+                    unit.push_line(Line::Directive(directive));
+                }
+                continue;
+            }
+
+            let line = raw.trim();
+            if line.is_empty() {
+                unit.push_line(Line::Blank);
+            } else if line.starts_with('#') || line.starts_with(';') {
+                unit.push_line(Line::Comment(raw.to_string()));
+            } else if line.starts_with('[') {
+                if !line.ends_with(']') || line.len() < 3 {
+                    return Err(ParseError {
+                        line: index + 1,
+                        message: format!("invalid section header {line}"),
+                    });
+                }
+                unit.sections.push(Section::new(&line[1..line.len() - 1]));
+            } else if let Some((key, value)) = line.split_once('=') {
+                if unit.sections.is_empty() {
+                    return Err(ParseError {
+                        line: index + 1,
+                        message: "assignment outside of a section".to_string(),
+                    });
+                }
+                let key = key.trim();
+                if key.is_empty() || key.contains(char::is_whitespace) {
+                    return Err(ParseError {
+                        line: index + 1,
+                        message: format!("invalid directive name {key:?}"),
+                    });
+                }
+                let directive = Directive {
+                    key: key.to_string(),
+                    value: value.trim_start().to_string(),
+                };
+                if line.ends_with('\\') {
+                    continued = Some(directive);
+                } else {
+                    unit.push_line(Line::Directive(directive));
+                }
+            } else {
+                return Err(ParseError {
+                    line: index + 1,
+                    message: format!("expected Key=Value, found {line:?}"),
+                });
+            }
+        }
+
+        if let Some(directive) = continued {
+            unit.push_line(Line::Directive(directive));
+        }
+
+        Ok(unit)
+    }
+
+    fn push_line(&mut self, line: Line) {
+        match self.sections.last_mut() {
+            Some(section) => section.lines.push(line),
+            None => self.preamble.push(line),
+        }
+    }
+
+    /// Returns the named section, appending an empty one if it is missing.
+    pub fn section_or_insert(&mut self, name: &str) -> &mut Section {
+        let index = match self.sections.iter().position(|s| s.name == name) {
+            Some(index) => index,
+            None => {
+                if let Some(last) = self.sections.last_mut() {
+                    if !matches!(last.lines.last(), Some(Line::Blank)) {
+                        last.lines.push(Line::Blank);
+                    }
+                }
+                self.sections.push(Section::new(name));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[index]
+    }
+}
+
+impl fmt::Display for UnitFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_line = |f: &mut fmt::Formatter<'_>, line: &Line| match line {
+            Line::Directive(d) => writeln!(f, "{}={}", d.key, d.value),
+            Line::Comment(c) => writeln!(f, "{c}"),
+            Line::Blank => writeln!(f),
+        };
+
+        for line in &self.preamble {
+            write_line(f, line)?;
+        }
+        for section in &self.sections {
+            writeln!(f, "[{}]", section.name)?;
+            for line in &section.lines {
+                write_line(f, line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsed_units_render_as_written() {
+        let text = "# preamble\n\n[Unit]\nDescription=Web\n; note\n\n\
+                    [Service]\nExecStart=/usr/bin/web \\\n    --port 80\n";
+        let unit = UnitFile::parse(text).unwrap();
+
+        assert_eq!(
+            unit.preamble,
+            [Line::Comment("# preamble".into()), Line::Blank]
+        );
+        assert_eq!(unit.sections[0].lines[1], Line::Comment("; note".into()));
+        assert_eq!(
+            unit.sections[1].values("ExecStart"),
+            ["/usr/bin/web \\\n    --port 80"]
+        );
+        assert_eq!(unit.to_string(), text);
+    }
+
+    #[test]
+    fn a_continuation_at_the_end_is_kept() {
+        let unit = UnitFile::parse("[Service]\nExecStart=/usr/bin/web \\").unwrap();
+        assert_eq!(unit.sections[0].values("ExecStart"), ["/usr/bin/web \\"]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |text| UnitFile::parse(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error("[Unit]\n[Service"),
+            "line 2: invalid section header [Service"
+        );
+        assert_eq!(error("[]"), "line 1: invalid section header []");
+        assert_eq!(
+            error("Description=Web"),
+            "line 1: assignment outside of a section"
+        );
+        assert_eq!(
+            error("[Unit]\nDescription Web"),
+            "line 2: expected Key=Value, found \"Description Web\""
+        );
+        assert_eq!(
+            error("[Unit]\nNo Key=x"),
+            "line 2: invalid directive name \"No Key\""
+        );
+    }
+
+    #[test]
+    fn set_keeps_the_first_position_and_drops_the_rest() {
+        let mut unit =
+            UnitFile::parse("[Unit]\nAfter=a\nDescription=Web\nAfter=b\n# end\n").unwrap();
+        unit.sections[0].set("After", "c");
+        assert_eq!(
+            unit.to_string(),
+            "[Unit]\nAfter=c\nDescription=Web\n# end\n"
+        );
+
+        unit.sections[0].set("Wants", "d");
+        assert_eq!(
+            unit.to_string(),
+            "[Unit]\nAfter=c\nDescription=Web\nWants=d\n# end\n"
+        );
+    }
+
+    #[test]
+    fn push_goes_after_the_last_directive() {
+        let mut unit = UnitFile::parse("[Unit]\nAfter=a\n\n[Service]\n").unwrap();
+        unit.sections[0].push("After", "b");
+        unit.sections[1].push("Type", "simple");
+        assert_eq!(
+            unit.to_string(),
+            "[Unit]\nAfter=a\nAfter=b\n\n[Service]\nType=simple\n"
+        );
+    }
+}
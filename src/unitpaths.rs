@@ -0,0 +1,172 @@
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{scope::Scope, unittype::UnitType};
+
+/// Directories systemd loads system units from, highest priority first.
+pub const SYSTEM_UNIT_PATHS: [&str; 3] = [
+    "/etc/systemd/system",
+    "/run/systemd/system",
+    "/usr/lib/systemd/system",
+];
+
+/// Directories every user manager loads units from, highest priority first.
+pub const GLOBAL_USER_UNIT_PATHS: [&str; 3] = [
+    "/etc/systemd/user",
+    "/run/systemd/user",
+    "/usr/lib/systemd/user",
+];
+
+/// Directories systemd loads user units from, highest priority first.
+pub fn user_unit_paths() -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if let Some(config_home) = config_home() {
+        paths.push(config_home.join("systemd/user"));
+    }
+    paths.extend(GLOBAL_USER_UNIT_PATHS.iter().map(PathBuf::from));
+    paths
+}
+
+/// Moves an absolute path below `root`, like `systemctl --root` does.
+pub fn rebase(root: Option<&Path>, path: &Path) -> PathBuf {
+    match root {
+        Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+        None => path.to_path_buf(),
+    }
+}
+
+/// $XDG_CONFIG_HOME, falling back to ~/.config.
+pub fn config_home() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    }
+}
+
+/// The file behind `unit`, which is either a path to a unit file or a unit name
+/// looked up like `find_installed_units` does. A name without a type suffix is
+/// taken to be a service.
+pub fn resolve_unit(root: Option<&Path>, unit: &str) -> Option<PathBuf> {
+    if unit.contains('/') {
+        let path = PathBuf::from(unit);
+        return path.is_file().then_some(path);
+    }
+    let name = match UnitType::from_file_name(unit) {
+        Some(_) => unit.to_string(),
+        None => UnitType::Service.file_name(unit),
+    };
+    find_installed_units(root)
+        .into_iter()
+        .find(|(found, _)| *found == name)
+        .map(|(_, path)| PathBuf::from(path))
+}
+
+/// Every unit file found in the system and user search paths as (unit name, path).
+///
+/// Like systemd, a unit found in a higher priority directory hides units of the
+/// same name further down the list of its manager; the system and user managers
+/// are separate, so a name can show up once for each. Masked units (symlinks to
+/// /dev/null) are skipped. With a `root`, the directories are looked up inside
+/// it and the current user's own unit directory is left out.
+pub fn find_installed_units(root: Option<&Path>) -> Vec<(String, String)> {
+    let mut units = vec![];
+
+    let user_paths = match root {
+        Some(_) => GLOBAL_USER_UNIT_PATHS.iter().map(PathBuf::from).collect(),
+        None => user_unit_paths(),
+    };
+    let system_paths = SYSTEM_UNIT_PATHS.iter().map(PathBuf::from).collect();
+
+    for search_paths in [system_paths, user_paths] {
+        let mut seen = HashSet::new();
+        for dir in search_paths {
+            let mut found = units_in_dir(&rebase(root, &dir));
+            found.sort();
+            for (name, path) in found {
+                if seen.insert(name.clone()) {
+                    units.push((name, path));
+                }
+            }
+        }
+    }
+
+    // Stable, so a system unit stays ahead of a user unit of the same name.
+    units.sort_by(|a, b| a.0.cmp(&b.0));
+    units
+}
+
+/// Scope whose search paths `path` was found in: the system scope, the
+/// current user's own directory, or the directories shared by all users.
+pub fn scope_of(root: Option<&Path>, path: &Path) -> Option<Scope> {
+    let dir = path.parent()?;
+    let within = |dirs: &[&str]| dirs.iter().any(|d| rebase(root, Path::new(d)) == dir);
+    if within(&SYSTEM_UNIT_PATHS) {
+        Some(Scope::System)
+    } else if within(&GLOBAL_USER_UNIT_PATHS) {
+        Some(Scope::GlobalUser)
+    } else if root.is_none() && Scope::User.unit_dir().is_some_and(|d| d == dir) {
+        Some(Scope::User)
+    } else {
+        None
+    }
+}
+
+fn units_in_dir(dir: &Path) -> Vec<(String, String)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            UnitType::from_file_name(&name)?;
+            // is_file() follows symlinks, so masked units pointing at /dev/null drop out here.
+            let path = entry.path();
+            if !path.is_file() {
+                return None;
+            }
+            Some((name, path.to_string_lossy().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn system_and_user_units_of_the_same_name_are_both_found() {
+        let root = env::temp_dir().join(format!("css-unitpaths-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in [
+            "etc/systemd/system",
+            "usr/lib/systemd/system",
+            "etc/systemd/user",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("foo.service"), "[Unit]\n").unwrap();
+        }
+
+        let units = find_installed_units(Some(&root));
+        let found: Vec<_> = units
+            .iter()
+            .map(|(name, path)| (name.as_str(), scope_of(Some(&root), Path::new(path))))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("foo.service", Some(Scope::System)),
+                ("foo.service", Some(Scope::GlobalUser)),
+            ]
+        );
+        // The vendor copy is hidden by the one in /etc.
+        assert!(units[0].1.ends_with("etc/systemd/system/foo.service"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+
+use crate::unitfile::UnitFile;
+
+/// The kinds of unit this program can create.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitType {
+    #[default]
+    Service,
+    Timer,
+    Socket,
+    Path,
+    Mount,
+    Automount,
+    Target,
+}
+
+impl UnitType {
+    pub const ALL: [UnitType; 7] = [
+        UnitType::Service,
+        UnitType::Timer,
+        UnitType::Socket,
+        UnitType::Path,
+        UnitType::Mount,
+        UnitType::Automount,
+        UnitType::Target,
+    ];
+
+    /// File name suffix without the leading dot, e.g. "timer".
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            UnitType::Service => "service",
+            UnitType::Timer => "timer",
+            UnitType::Socket => "socket",
+            UnitType::Path => "path",
+            UnitType::Mount => "mount",
+            UnitType::Automount => "automount",
+            UnitType::Target => "target",
+        }
+    }
+
+    /// Works out the unit type from a file or unit name such as "backup.timer".
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let (_, suffix) = name.rsplit_once('.')?;
+        Self::ALL.into_iter().find(|t| t.suffix() == suffix)
+    }
+
+    /// Appends the suffix to `name` unless it is already there.
+    pub fn file_name(&self, name: &str) -> String {
+        let suffix = format!(".{}", self.suffix());
+        if name.ends_with(&suffix) {
+            name.to_string()
+        } else {
+            name.to_string() + &suffix
+        }
+    }
+
+    /// Section holding the type-specific settings, e.g. [Timer]. Targets have none.
+    pub fn type_section(&self) -> Option<&'static str> {
+        match self {
+            UnitType::Service => Some("Service"),
+            UnitType::Timer => Some("Timer"),
+            UnitType::Socket => Some("Socket"),
+            UnitType::Path => Some("Path"),
+            UnitType::Mount => Some("Mount"),
+            UnitType::Automount => Some("Automount"),
+            UnitType::Target => None,
+        }
+    }
+
+    /// Target the unit is usually pulled in by from its [Install] section.
+    pub fn default_wanted_by(&self) -> &'static str {
+        match self {
+            UnitType::Service | UnitType::Target => "multi-user.target",
+            UnitType::Timer => "timers.target",
+            UnitType::Socket => "sockets.target",
+            UnitType::Path => "paths.target",
+            UnitType::Mount | UnitType::Automount => "local-fs.target",
+        }
+    }
+
+    /// Skeleton used when the template directory has no template of this type.
+    pub fn default_template(&self) -> String {
+        let body = match self {
+            UnitType::Service => "[Service]\nType=simple\nExecStart=/bin/true\nRestart=no\n",
+            UnitType::Timer => "[Timer]\nOnCalendar=daily\nPersistent=true\nUnit=\n",
+            UnitType::Socket => "[Socket]\nListenStream=\nAccept=no\n",
+            UnitType::Path => "[Path]\nPathChanged=\nUnit=\n",
+            UnitType::Mount => "[Mount]\nWhat=\nWhere=\nType=\nOptions=defaults\n",
+            UnitType::Automount => "[Automount]\nWhere=\nTimeoutIdleSec=0\n",
+            UnitType::Target => "",
+        };
+        let separator = if body.is_empty() { "" } else { "\n" };
+
+        format!(
+            "[Unit]\nDescription=\n\n{body}{separator}[Install]\nWantedBy={}\n",
+            self.default_wanted_by()
+        )
+    }
+
+    /// Checks the sections and required directives for this unit type.
+    pub fn validate(&self, unit: &UnitFile) -> Vec<String> {
+        let mut problems = vec![];
+
+        for section in &unit.sections {
+            let allowed = section.name == "Unit"
+                || section.name == "Install"
+                || section.name.starts_with("X-")
+                || Some(section.name.as_str()) == self.type_section();
+            if !allowed {
+                problems.push(format!(
+                    "[{}] is not valid in a .{} unit",
+                    section.name,
+                    self.suffix()
+                ));
+            }
+        }
+
+        let Some(type_section) = self.type_section() else {
+            return problems;
+        };
+        let Some(section) = unit.sections.iter().find(|s| s.name == type_section) else {
+            problems.push(format!(
+                "missing [{type_section}] section for a .{} unit",
+                self.suffix()
+            ));
+            return problems;
+        };
+
+        let has_any = |keys: &[&str]| {
+            section
+                .directives()
+                .any(|d| keys.contains(&d.key.as_str()) && !d.value.is_empty())
+        };
+
+        let required: &[&str] = match self {
+            UnitType::Service => {
+                let oneshot = section.values("Type").last() == Some(&"oneshot");
+                if oneshot {
+                    &[]
+                } else {
+                    &["ExecStart"]
+                }
+            }
+            UnitType::Timer => &[
+                "OnCalendar",
+                "OnActiveSec",
+                "OnBootSec",
+                "OnStartupSec",
+                "OnUnitActiveSec",
+                "OnUnitInactiveSec",
+            ],
+            UnitType::Socket => &[
+                "ListenStream",
+                "ListenDatagram",
+                "ListenSequentialPacket",
+                "ListenFIFO",
+                "ListenSpecial",
+                "ListenNetlink",
+                "ListenMessageQueue",
+                "ListenUSBFunction",
+            ],
+            UnitType::Path => &[
+                "PathExists",
+                "PathExistsGlob",
+                "PathChanged",
+                "PathModified",
+                "DirectoryNotEmpty",
+            ],
+            UnitType::Mount => &["What"],
+            UnitType::Automount => &["Where"],
+            UnitType::Target => &[],
+        };
+
+        if !required.is_empty() && !has_any(required) {
+            problems.push(format!("[{type_section}] needs {}", required.join(" or ")));
+        }
+        if *self == UnitType::Mount && !has_any(&["Where"]) {
+            problems.push("[Mount] needs Where".to_string());
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(unit_type: UnitType, text: &str) -> Vec<String> {
+        unit_type.validate(&UnitFile::parse(text).unwrap())
+    }
+
+    #[test]
+    fn valid_units_have_no_problems() {
+        let units = [
+            (UnitType::Service, "[Service]\nExecStart=/usr/bin/web\n"),
+            (UnitType::Service, "[Service]\nType=oneshot\n"),
+            (UnitType::Timer, "[Timer]\nOnBootSec=5min\n"),
+            (UnitType::Socket, "[Socket]\nListenStream=8080\n"),
+            (UnitType::Path, "[Path]\nDirectoryNotEmpty=/var/spool\n"),
+            (UnitType::Mount, "[Mount]\nWhat=/dev/sdb1\nWhere=/data\n"),
+            (UnitType::Automount, "[Automount]\nWhere=/data\n"),
+            (
+                UnitType::Target,
+                "[Unit]\nDescription=Apps\n\n[Install]\nWantedBy=multi-user.target\n",
+            ),
+        ];
+        for (unit_type, text) in units {
+            assert_eq!(problems(unit_type, text), Vec::<String>::new(), "{text}");
+        }
+    }
+
+    #[test]
+    fn default_templates_only_lack_their_values() {
+        for unit_type in UnitType::ALL {
+            let unit = UnitFile::parse(&unit_type.default_template()).unwrap();
+            let problems = unit_type.validate(&unit);
+            assert!(
+                problems.iter().all(|p| p.contains(" needs ")),
+                "{unit_type:?}: {problems:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_units_are_explained() {
+        let units = [
+            (UnitType::Service, "[Service]\nExecStart=\n", "[Service] needs ExecStart"),
+            (UnitType::Service, "[Unit]\nDescription=Web\n", "missing [Service] section for a .service unit"),
+            (UnitType::Timer, "[Timer]\nPersistent=true\n", "[Timer] needs OnCalendar or OnActiveSec or OnBootSec or OnStartupSec or OnUnitActiveSec or OnUnitInactiveSec"),
+            (UnitType::Socket, "[Socket]\nListenStream=8080\n[Service]\nExecStart=/usr/bin/web\n", "[Service] is not valid in a .socket unit"),
+            (UnitType::Path, "[Path]\nUnit=sync.service\n", "[Path] needs PathExists or PathExistsGlob or PathChanged or PathModified or DirectoryNotEmpty"),
+            (UnitType::Mount, "[Mount]\nWhat=/dev/sdb1\n", "[Mount] needs Where"),
+            (UnitType::Mount, "[Mount]\nWhere=/data\n", "[Mount] needs What"),
+            (UnitType::Automount, "[Automount]\nTimeoutIdleSec=0\n", "[Automount] needs Where"),
+            (UnitType::Target, "[Target]\n", "[Target] is not valid in a .target unit"),
+        ];
+        for (unit_type, text, problem) in units {
+            assert_eq!(problems(unit_type, text), [problem], "{text}");
+        }
+    }
+
+    #[test]
+    fn extension_sections_are_allowed() {
+        assert!(problems(UnitType::Target, "[X-Backup]\nKeep=7\n").is_empty());
+    }
+
+    #[test]
+    fn file_names_get_their_suffix_once() {
+        for unit_type in UnitType::ALL {
+            let name = unit_type.file_name("backup");
+            assert_eq!(name, format!("backup.{}", unit_type.suffix()));
+            assert_eq!(unit_type.file_name(&name), name);
+            assert_eq!(UnitType::from_file_name(&name), Some(unit_type));
+        }
+        assert_eq!(
+            UnitType::Timer.file_name("backup.service"),
+            "backup.service.timer"
+        );
+    }
+
+    #[test]
+    fn unknown_suffixes_have_no_type() {
+        assert_eq!(UnitType::from_file_name("backup.slice"), None);
+        assert_eq!(UnitType::from_file_name("backup"), None);
+        assert_eq!(UnitType::from_file_name("backup.service.bak"), None);
+        assert_eq!(
+            UnitType::from_file_name("web@.service"),
+            Some(UnitType::Service)
+        );
+    }
+}
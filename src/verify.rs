@@ -0,0 +1,218 @@
+use std::{env, fs, io, path::Path, process::Command};
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{scope::Scope, writer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Ignored by systemd, the unit still loads.
+    Warning,
+    /// Stops the unit from loading or running.
+    Error,
+}
+
+/// One complaint about a unit, tied to a line when the verifier names one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// 1-based line number in the rendered unit.
+    pub line: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+    /// Id of the lint rule that raised it, see lint.rs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<&'static str>,
+}
+
+/// Checks a rendered unit before it is saved.
+///
+/// The TUI only talks to this trait, so tests can hand it canned output
+/// instead of running systemd-analyze.
+pub trait Verifier {
+    fn verify(
+        &mut self,
+        scope: Scope,
+        unit_name: &str,
+        contents: &str,
+    ) -> io::Result<Vec<Diagnostic>>;
+}
+
+/// `systemd-analyze verify` if it is installed.
+pub fn default_verifier() -> Option<Box<dyn Verifier>> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .any(|dir| dir.join("systemd-analyze").is_file())
+        .then(|| Box::new(SystemdAnalyze) as Box<dyn Verifier>)
+}
+
+/// `Verifier` that runs `systemd-analyze verify` on a temporary copy of the unit.
+pub struct SystemdAnalyze;
+
+impl Verifier for SystemdAnalyze {
+    fn verify(
+        &mut self,
+        scope: Scope,
+        unit_name: &str,
+        contents: &str,
+    ) -> io::Result<Vec<Diagnostic>> {
+        // The copy keeps the unit's name, systemd derives the unit type from it.
+        let dir = writer::private_temp_dir("create-systemd-service")?;
+        let path = dir.join(unit_name);
+        fs::write(&path, contents)?;
+
+        let mut command = Command::new("systemd-analyze");
+        if scope != Scope::System {
+            command.arg("--user");
+        }
+        let output = command.args(["verify", "--man=no"]).arg(&path).output();
+        let _ = fs::remove_dir_all(&dir);
+        let output = output?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Ok(parse_output(
+            &stderr,
+            &path,
+            unit_name,
+            output.status.success(),
+        ))
+    }
+}
+
+/// Reads systemd-analyze's complaints about the unit at `path`.
+///
+/// `<path>:<line>: message` lines are about single settings, which systemd
+/// ignores or warns about while still loading the unit; `<unit>: message`
+/// lines are about the unit as a whole, e.g. a missing executable. Anything
+/// else only counts when the verification failed.
+pub fn parse_output(output: &str, path: &Path, unit_name: &str, success: bool) -> Vec<Diagnostic> {
+    let line_re = Regex::new(r"^(?P<file>.+?):(?P<line>\d+): (?P<message>.*)$").unwrap();
+    let path = path.to_string_lossy();
+    let unit_prefix = format!("{unit_name}: ");
+
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            if let Some(captures) = line_re.captures(line) {
+                if captures["file"] == *path {
+                    return Some(Diagnostic {
+                        line: captures["line"].parse().ok(),
+                        severity: Severity::Warning,
+                        message: captures["message"].to_string(),
+                        rule: None,
+                    });
+                }
+            }
+            if let Some(message) = line.strip_prefix(&unit_prefix) {
+                return Some(Diagnostic {
+                    line: None,
+                    severity: Severity::Error,
+                    message: message.to_string(),
+                    rule: None,
+                });
+            }
+            (!success).then(|| Diagnostic {
+                line: None,
+                severity: Severity::Error,
+                message: line.to_string(),
+                rule: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(line: Option<usize>, severity: Severity, message: &str) -> Diagnostic {
+        Diagnostic {
+            line,
+            severity,
+            message: message.to_string(),
+            rule: None,
+        }
+    }
+
+    #[test]
+    fn settings_are_warnings_and_unit_problems_errors() {
+        // systemd-analyze verify --man=no /tmp/lt/fix.service, exit status 1
+        let output = "\
+/tmp/lt/fix.service:3: Unknown key 'Bogus' in section [Unit], ignoring.
+/tmp/lt/fix.service:7: Unit uses MemoryLimit=; please use MemoryMax= instead. Support for MemoryLimit= will be removed soon.
+fix.service: Command /usr/bin/does-not-exist is not executable: No such file or directory
+";
+        let path = Path::new("/tmp/lt/fix.service");
+        assert_eq!(
+            parse_output(output, path, "fix.service", false),
+            [
+                diagnostic(
+                    Some(3),
+                    Severity::Warning,
+                    "Unknown key 'Bogus' in section [Unit], ignoring."
+                ),
+                diagnostic(
+                    Some(7),
+                    Severity::Warning,
+                    "Unit uses MemoryLimit=; please use MemoryMax= instead. Support for MemoryLimit= will be removed soon."
+                ),
+                diagnostic(
+                    None,
+                    Severity::Error,
+                    "Command /usr/bin/does-not-exist is not executable: No such file or directory"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn warnings_only_when_verify_succeeds() {
+        // Exit status 0.
+        let output =
+            "/tmp/lt/ok.service:5: Failed to parse service restart specifier, ignoring: sometimes\n";
+        assert_eq!(
+            parse_output(output, Path::new("/tmp/lt/ok.service"), "ok.service", true),
+            [diagnostic(
+                Some(5),
+                Severity::Warning,
+                "Failed to parse service restart specifier, ignoring: sometimes"
+            )]
+        );
+    }
+
+    #[test]
+    fn other_lines_count_when_verify_fails() {
+        // Exit status 1.
+        let output = "\
+empty.service: Service has no ExecStart=, ExecStop=, or SuccessAction=. Refusing.
+Unit empty.service has a bad unit file setting.
+";
+        let path = Path::new("/tmp/lt/empty.service");
+        assert_eq!(
+            parse_output(output, path, "empty.service", false),
+            [
+                diagnostic(
+                    None,
+                    Severity::Error,
+                    "Service has no ExecStart=, ExecStop=, or SuccessAction=. Refusing."
+                ),
+                diagnostic(
+                    None,
+                    Severity::Error,
+                    "Unit empty.service has a bad unit file setting."
+                ),
+            ]
+        );
+        assert!(parse_output(output, path, "empty.service", true).len() == 1);
+    }
+
+    #[test]
+    fn lines_about_other_files_are_not_tied_to_the_unit() {
+        let output =
+            "/etc/systemd/system/other.service:2: Unknown key 'X' in section [Unit], ignoring.\n";
+        let path = Path::new("/tmp/lt/fix.service");
+        assert!(parse_output(output, path, "fix.service", true).is_empty());
+    }
+}
@@ -0,0 +1,519 @@
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    fs::{self, DirBuilder, File, OpenOptions, Permissions},
+    hash::{BuildHasher, Hasher},
+    io::{self, Write},
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::escalate::Escalator;
+
+/// Mode systemd expects for unit files.
+const UNIT_FILE_MODE: u32 = 0o644;
+
+/// What `write_unit` did on disk.
+pub struct WriteOutcome {
+    pub path: PathBuf,
+    /// Copy of the previous version, if the file already existed.
+    pub backup: Option<PathBuf>,
+}
+
+/// Replaces `path` with `contents` without ever leaving a partly written file.
+///
+/// The contents go to a temporary file in the same directory, which is fsynced,
+/// given mode 0644 and renamed over the target. An existing file is first copied
+/// to `<name>.<timestamp>.bak`, which systemd ignores, or `<name>.<timestamp>.<n>.bak`
+/// if that is taken by an earlier save in the same second.
+pub fn write_unit(path: &Path, contents: &str) -> io::Result<WriteOutcome> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no directory"))?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy()
+        .to_string();
+
+    fs::create_dir_all(dir)?;
+
+    let backup = if path.exists() {
+        Some(back_up(
+            path,
+            &dir.join(format!("{file_name}.{}", timestamp())),
+        )?)
+    } else {
+        None
+    };
+
+    let temp_path = dir.join(format!(".{file_name}.{}.tmp", process::id()));
+    if let Err(e) = write_synced(&temp_path, contents) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    // Make the rename itself durable.
+    File::open(dir)?.sync_all()?;
+
+    Ok(WriteOutcome {
+        path: path.to_path_buf(),
+        backup,
+    })
+}
+
+/// Copies `path` to `<stem>.bak`, or to the first free `<stem>.<n>.bak`, never
+/// replacing an earlier backup.
+fn back_up(path: &Path, stem: &Path) -> io::Result<PathBuf> {
+    let mut n = 0;
+    loop {
+        let backup = match n {
+            0 => PathBuf::from(format!("{}.bak", stem.display())),
+            n => PathBuf::from(format!("{}.{n}.bak", stem.display())),
+        };
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
+        {
+            Ok(mut file) => {
+                io::copy(&mut File::open(path)?, &mut file)?;
+                file.set_permissions(fs::metadata(path)?.permissions())?;
+                return Ok(backup);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Shell script `write_unit_as_root` runs as root, with the target path and
+/// the backup name without its `.bak` as arguments and the contents on
+/// standard input. It takes the same steps as `write_unit` and prints the
+/// backup it made, if any.
+const WRITE_SCRIPT: &str = r#"set -e
+dir=$(dirname "$1")
+tmp="$dir/.$(basename "$1").$$.tmp"
+mkdir -p "$dir"
+if [ -e "$1" ]; then
+  backup="$2.bak" n=1
+  while [ -e "$backup" ]; do backup="$2.$n.bak" n=$((n + 1)); done
+  cp -p "$1" "$backup"
+  printf '%s' "$backup"
+fi
+trap 'rm -f "$tmp"' EXIT
+cat > "$tmp"
+chmod 0644 "$tmp"
+sync "$tmp"
+mv -f "$tmp" "$1"
+sync "$dir"
+"#;
+
+/// `write_unit` for directories only root can write to. Only this single
+/// script runs elevated; the contents are rendered by the unprivileged app.
+pub fn write_unit_as_root(
+    escalator: &mut dyn Escalator,
+    path: &Path,
+    contents: &str,
+) -> io::Result<WriteOutcome> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy()
+        .to_string();
+    let stem = path.with_file_name(format!("{file_name}.{}", timestamp()));
+
+    let backup = escalator.run(
+        "sh",
+        &[
+            "-c",
+            WRITE_SCRIPT,
+            "sh",
+            &path.to_string_lossy(),
+            &stem.to_string_lossy(),
+        ],
+        Some(contents),
+    )?;
+
+    Ok(WriteOutcome {
+        path: path.to_path_buf(),
+        backup: (!backup.is_empty()).then(|| PathBuf::from(backup)),
+    })
+}
+
+/// `write_unit`, going through `escalator` when only root can write to `path`.
+pub fn write_unit_with(
+    escalator: Option<&mut (dyn Escalator + '_)>,
+    path: &Path,
+    contents: &str,
+) -> io::Result<WriteOutcome> {
+    match escalator {
+        Some(escalator) if escalator.needed_for(path) => {
+            write_unit_as_root(escalator, path, contents)
+        }
+        _ => write_unit(path, contents),
+    }
+}
+
+/// Writes every `(path, contents)` pair or none of them: when one write fails,
+/// the ones before it are undone and the error is returned.
+pub fn write_all_with(
+    mut escalator: Option<&mut (dyn Escalator + '_)>,
+    units: &[(PathBuf, String)],
+) -> io::Result<Vec<WriteOutcome>> {
+    let mut outcomes = vec![];
+    for (path, contents) in units {
+        match write_unit_with(escalator.as_deref_mut(), path, contents) {
+            Ok(outcome) => outcomes.push(outcome),
+            Err(e) => {
+                let e = io::Error::new(e.kind(), format!("{}: {e}", path.display()));
+                for outcome in outcomes.iter().rev() {
+                    if let Err(undo) = undo_write(escalator.as_deref_mut(), outcome) {
+                        return Err(io::Error::new(
+                            e.kind(),
+                            format!(
+                                "{e}; also unable to roll back {}: {undo}",
+                                outcome.path.display()
+                            ),
+                        ));
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(outcomes)
+}
+
+/// Puts back what was at `outcome.path` before the write: the backup if the
+/// file existed, nothing otherwise.
+pub fn undo_write(
+    escalator: Option<&mut (dyn Escalator + '_)>,
+    outcome: &WriteOutcome,
+) -> io::Result<()> {
+    let path_arg = outcome.path.to_string_lossy();
+    match (escalator, &outcome.backup) {
+        (Some(escalator), Some(backup)) if escalator.needed_for(&outcome.path) => {
+            let backup_arg = backup.to_string_lossy();
+            escalator.run("mv", &["-f", "--", &backup_arg, &path_arg], None)?;
+        }
+        (Some(escalator), None) if escalator.needed_for(&outcome.path) => {
+            escalator.run("rm", &["-f", "--", &path_arg], None)?;
+        }
+        (_, Some(backup)) => fs::rename(backup, &outcome.path)?,
+        (_, None) => fs::remove_file(&outcome.path)?,
+    }
+    Ok(())
+}
+
+fn write_synced(path: &Path, contents: &str) -> io::Result<()> {
+    let mut f = OpenOptions::new().write(true).create_new(true).open(path)?;
+    f.write_all(contents.as_bytes())?;
+    f.set_permissions(Permissions::from_mode(UNIT_FILE_MODE))?;
+    f.sync_all()
+}
+
+/// Every backup `write_unit` left next to `path` with its timestamp, oldest first.
+pub fn backups(path: &Path) -> Vec<(String, PathBuf)> {
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return vec![];
+    };
+    let prefix = format!("{}.", file_name.to_string_lossy());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut backups: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let rest = name.strip_prefix(&prefix)?.strip_suffix(".bak")?;
+            let (stamp, n) = match rest.split_once('.') {
+                Some((stamp, n)) => (stamp, n.parse::<u32>().ok()?),
+                None => (rest, 0),
+            };
+            (stamp.len() == 15 && stamp.as_bytes()[8] == b'T')
+                .then(|| ((stamp.to_string(), n), dir.join(&name)))
+        })
+        .collect();
+    // The timestamps sort chronologically, the counter orders saves within a second.
+    backups.sort();
+    backups
+        .into_iter()
+        .map(|((stamp, _), path)| (stamp, path))
+        .collect()
+}
+
+/// Creates a directory in the temporary directory that only the current user
+/// can enter. Its name cannot be guessed and it must not exist yet, so nothing
+/// another user planted in /tmp is followed, even when running as root.
+pub fn private_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    loop {
+        let random = RandomState::new().build_hasher().finish();
+        let dir = env::temp_dir().join(format!("{prefix}-{}-{random:016x}", process::id()));
+        match DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Current UTC time as YYYYMMDDTHHMMSS.
+pub fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escalate::RecordingEscalator;
+
+    /// An empty directory of its own for each test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("css-writer-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn args(call: &(String, Vec<String>, Option<String>)) -> Vec<&str> {
+        call.1.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn root_write_is_a_single_script() {
+        let dir = test_dir("root-write");
+        let path = dir.join("foo.service");
+        let mut escalator = RecordingEscalator::new(&dir);
+
+        let outcome = write_unit_as_root(&mut escalator, &path, "[Unit]\n").unwrap();
+
+        assert_eq!(escalator.calls.len(), 1);
+        let call = &escalator.calls[0];
+        assert_eq!(call.0, "sh");
+        assert_eq!(
+            args(call)[..4],
+            ["-c", WRITE_SCRIPT, "sh", path.to_str().unwrap()]
+        );
+        // Backups go next to the unit, as `<file name>.<stamp>[.<n>].bak`.
+        let stem = args(call)[4].strip_prefix(&format!("{}.", path.display()));
+        assert!(stem.is_some_and(|stamp| stamp.len() == 15));
+        assert_eq!(call.2.as_deref(), Some("[Unit]\n"));
+        assert!(outcome.backup.is_none(), "nothing to back up");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// `Escalator` that runs the commands as the current user, to try the
+    /// root script for real.
+    struct RunDirectly;
+
+    impl Escalator for RunDirectly {
+        fn run(&mut self, program: &str, args: &[&str], input: Option<&str>) -> io::Result<String> {
+            let mut child = process::Command::new(program)
+                .args(args)
+                .stdin(process::Stdio::piped())
+                .stdout(process::Stdio::piped())
+                .spawn()?;
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(input.unwrap_or_default().as_bytes())?;
+            let output = child.wait_with_output()?;
+            assert!(output.status.success());
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        }
+    }
+
+    #[test]
+    fn root_script_keeps_every_backup() {
+        let dir = test_dir("root-backups");
+        let path = dir.join("foo.service");
+        fs::write(&path, "first").unwrap();
+
+        let second = write_unit_as_root(&mut RunDirectly, &path, "second").unwrap();
+        let third = write_unit_as_root(&mut RunDirectly, &path, "third").unwrap();
+
+        let (second, third) = (second.backup.unwrap(), third.backup.unwrap());
+        assert_ne!(second, third);
+        assert_eq!(fs::read_to_string(&second).unwrap(), "first");
+        assert_eq!(fs::read_to_string(&third).unwrap(), "second");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, UNIT_FILE_MODE);
+        assert_eq!(backups(&path).pop().map(|(_, backup)| backup), Some(third));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saves_in_the_same_second_keep_every_backup() {
+        let dir = test_dir("backups");
+        let path = dir.join("foo.service");
+        fs::write(&path, "first").unwrap();
+
+        let backups: Vec<_> = ["second", "third", "fourth"]
+            .iter()
+            .map(|contents| write_unit(&path, contents).unwrap().backup.unwrap())
+            .collect();
+
+        let contents: Vec<_> = backups
+            .iter()
+            .map(|backup| fs::read_to_string(backup).unwrap())
+            .collect();
+        assert_eq!(contents, ["first", "second", "third"]);
+        let found: Vec<_> = super::backups(&path).into_iter().map(|(_, b)| b).collect();
+        assert_eq!(found, backups);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, UNIT_FILE_MODE);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn backups_sort_by_time_then_counter() {
+        let dir = test_dir("backup-order");
+        let path = dir.join("foo.service");
+        for name in [
+            "foo.service.20240102T000000.bak",
+            "foo.service.20240101T000000.10.bak",
+            "foo.service.20240101T000000.2.bak",
+            "foo.service.20240101T000000.bak",
+            "foo.service.d",
+            "foo.service.old.bak",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let names: Vec<_> = backups(&path)
+            .iter()
+            .map(|(_, b)| b.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "foo.service.20240101T000000.bak",
+                "foo.service.20240101T000000.2.bak",
+                "foo.service.20240101T000000.10.bak",
+                "foo.service.20240102T000000.bak",
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn private_temp_dirs_are_new_and_closed_to_others() {
+        let first = private_temp_dir("css-writer-private").unwrap();
+        let second = private_temp_dir("css-writer-private").unwrap();
+
+        assert_ne!(first, second);
+        for dir in [first, second] {
+            let metadata = fs::symlink_metadata(&dir).unwrap();
+            assert!(metadata.is_dir());
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+            fs::remove_dir(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn writable_paths_skip_the_escalator() {
+        let dir = test_dir("direct");
+        let path = dir.join("foo.service");
+        let mut escalator = RecordingEscalator::new(&dir.join("elsewhere"));
+
+        write_unit_with(Some(&mut escalator), &path, "[Unit]\n").unwrap();
+
+        assert!(escalator.calls.is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[Unit]\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, UNIT_FILE_MODE);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undo_moves_the_backup_back_or_removes_the_file() {
+        let dir = test_dir("undo");
+        let path = dir.join("foo.service");
+        let backup = dir.join("foo.service.1.bak");
+        let mut escalator = RecordingEscalator::new(&dir);
+
+        let restored = WriteOutcome {
+            path: path.clone(),
+            backup: Some(backup.clone()),
+        };
+        undo_write(Some(&mut escalator), &restored).unwrap();
+        let created = WriteOutcome {
+            path: path.clone(),
+            backup: None,
+        };
+        undo_write(Some(&mut escalator), &created).unwrap();
+
+        let (path, backup) = (path.to_str().unwrap(), backup.to_str().unwrap());
+        let calls: Vec<_> = escalator
+            .calls
+            .iter()
+            .map(|call| (call.0.as_str(), args(call), call.2.clone()))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("mv", vec!["-f", "--", backup, path], None),
+                ("rm", vec!["-f", "--", path], None),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_rolls_back_earlier_ones() {
+        let dir = test_dir("rollback");
+        let first = dir.join("a.service");
+        let second = dir.join("b.service");
+        let mut escalator = RecordingEscalator::new(&dir);
+        escalator.fail_on = Some("b.service".to_string());
+
+        let units = [
+            (first.clone(), "a".to_string()),
+            (second.clone(), "b".to_string()),
+        ];
+        let e = write_all_with(Some(&mut escalator), &units)
+            .err()
+            .expect("second write fails");
+
+        assert!(e.to_string().starts_with(second.to_str().unwrap()));
+        let programs: Vec<_> = escalator.calls.iter().map(|c| c.0.as_str()).collect();
+        assert_eq!(programs, ["sh", "sh", "rm"]);
+        assert_eq!(
+            args(&escalator.calls[2]),
+            ["-f", "--", first.to_str().unwrap()]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}